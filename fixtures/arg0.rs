@@ -0,0 +1,3 @@
+fn main() {
+    println!("{}", std::env::args().next().unwrap_or_default());
+}
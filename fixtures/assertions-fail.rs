@@ -0,0 +1,3 @@
+let answer = 6 * 7;
+
+assert_eq!(answer, 41, "wrong answer");
@@ -0,0 +1,4 @@
+let numbers = vec![1, 2, 3];
+
+assert_eq!(numbers.iter().sum::<i32>(), 6);
+assert!(numbers.contains(&2), "missing 2");
@@ -0,0 +1,3 @@
+async fn main() {
+    println!("Hello from async!");
+}
@@ -0,0 +1,6 @@
+//# bin: name = "hello", path = "src/main.rs"
+//# bin: name = "bye", path = "src/main.rs"
+
+fn main() {
+    println!("{}", env!("CARGO_BIN_NAME"));
+}
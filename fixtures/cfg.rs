@@ -0,0 +1,6 @@
+fn main() {
+    #[cfg(mycfg)]
+    println!("mycfg enabled");
+    #[cfg(not(mycfg))]
+    println!("mycfg disabled");
+}
@@ -0,0 +1,3 @@
+fn greet() -> &'static str {
+    "Hello"
+}
@@ -0,0 +1,3 @@
+fn word() -> &'static str {
+    include_str!("word.txt").trim()
+}
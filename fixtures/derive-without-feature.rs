@@ -0,0 +1,13 @@
+//# serde = "1"
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn main() {
+    let _ = Point { x: 1, y: 2 };
+}
@@ -0,0 +1,6 @@
+//# edition = "2015"
+
+fn main() {
+    let await = 1;
+    println!("hello {}", await);
+}
@@ -0,0 +1,7 @@
+fn main() {
+    println!(
+        "{} {}",
+        env!("CARGO_PLAY_SRC_HASH"),
+        env!("CARGO_PLAY_VERSION")
+    );
+}
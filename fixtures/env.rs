@@ -0,0 +1,3 @@
+fn main() {
+    println!("{}", std::env::var("PLAY_ENV").unwrap_or_default());
+}
@@ -0,0 +1,20 @@
+#!/usr/bin/env cargo-play
+//# ---
+//# [dependencies]
+//# bitflags = "1.1.0"
+//#
+//# [profile.dev]
+//# opt-level = 1
+//# ---
+
+use bitflags::bitflags;
+
+bitflags! {
+    struct Flags: u32 {
+        const A = 0b00000001;
+    }
+}
+
+fn main() {
+    println!("{:?}", Flags::A);
+}
@@ -0,0 +1,3 @@
+fn helper() -> &'static str {
+    "Hello from include!"
+}
@@ -0,0 +1,5 @@
+const GREETING: &[u8] = include_bytes!("../greeting.txt");
+
+fn main() {
+    println!("{} bytes", GREETING.len());
+}
@@ -0,0 +1,5 @@
+const GREETING: &str = include_str!("../greeting.txt");
+
+fn main() {
+    print!("{}", GREETING);
+}
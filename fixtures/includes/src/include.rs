@@ -0,0 +1,5 @@
+include!("helper.rs");
+
+fn main() {
+    println!("{}", helper());
+}
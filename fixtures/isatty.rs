@@ -0,0 +1,5 @@
+use std::io::IsTerminal;
+
+fn main() {
+    println!("{}", std::io::stdout().is_terminal());
+}
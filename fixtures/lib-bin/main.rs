@@ -0,0 +1,5 @@
+use greeting::greeting;
+
+fn main() {
+    println!("{}", greeting("lib"));
+}
@@ -0,0 +1,6 @@
+use answer::answer;
+
+#[test]
+fn answer_is_42() {
+    assert_eq!(answer(), 42);
+}
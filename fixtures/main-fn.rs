@@ -0,0 +1,7 @@
+fn greet() {
+    println!("Hello from greet!");
+}
+
+fn answer() -> u32 {
+    42
+}
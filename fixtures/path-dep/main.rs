@@ -0,0 +1,5 @@
+//# greeter = { path = "greeter" }
+
+fn main() {
+    println!("{}", greeter::greet());
+}
@@ -0,0 +1,4 @@
+fn main() {
+    eprintln!("error[E0432]: unresolved import `serde_jsn`");
+    std::process::exit(1);
+}
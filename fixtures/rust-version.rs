@@ -0,0 +1,5 @@
+//# rust-version: 99.0
+
+fn main() {
+    println!("Hello from the future!");
+}
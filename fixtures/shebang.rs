@@ -0,0 +1,9 @@
+#!/usr/local/bin/cargo-play --release --quiet-on-success
+
+fn main() {
+    if cfg!(debug_assertions) {
+        println!("debug");
+    } else {
+        println!("release");
+    }
+}
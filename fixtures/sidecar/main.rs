@@ -0,0 +1,5 @@
+//# bitflags = "1.1.0"
+
+fn main() {
+    println!("Hello from a sidecar!");
+}
@@ -0,0 +1,7 @@
+use std::num::ParseIntError;
+
+fn main() -> Result<(), ParseIntError> {
+    let answer: u32 = "forty-two".parse()?;
+    println!("answer: {}", answer);
+    Ok(())
+}
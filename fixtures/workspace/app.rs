@@ -0,0 +1,5 @@
+//# greeting = { workspace = true }
+
+fn main() {
+    println!("{} World!", greeting::greeting());
+}
@@ -1,33 +1,118 @@
 use std::collections::HashSet;
+use std::path::Path;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use toml::value::{Table, Value};
 
 use crate::errors::CargoPlayError;
-use crate::opt::RustEdition;
+use crate::opt::{
+    package_name, Dependency, DependencyKind, ManifestFormat, PanicStrategy, ProfileSetting,
+    RustEdition,
+};
 
 #[derive(Clone, Debug, Serialize)]
 struct CargoPackage {
     name: String,
     version: String,
     edition: String,
+    #[serde(rename = "rust-version", skip_serializing_if = "Option::is_none")]
+    rust_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolver: Option<String>,
 }
 
 impl CargoPackage {
     fn new(name: String, edition: RustEdition) -> Self {
         Self {
-            name: name.to_lowercase(),
+            name: package_name(&name),
             version: "0.1.0".into(),
             edition: edition.into(),
+            rust_version: None,
+            resolver: edition.default_resolver().map(String::from),
         }
     }
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct LockedPackage {
+    pub(crate) name: String,
+    pub(crate) version: String,
+}
+
+/// The subset of `Cargo.lock` needed to report resolved dependency versions.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct CargoLock {
+    #[serde(default)]
+    pub(crate) package: Vec<LockedPackage>,
+}
+
 #[derive(Clone, Debug, Serialize)]
-pub(crate) struct CargoManifest {
+struct CargoTarget {
+    name: String,
+    path: String,
+}
+
+/// Where a dependency of the generated manifest is declared, from the highest precedence to the
+/// lowest: a dependency declared by several sources is taken from the first of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum DependencySource {
+    Header,
+    Frontmatter,
+    Sidecar,
+    ManifestInclude,
+    Project,
+    Inferred,
+}
+
+impl std::fmt::Display for DependencySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            DependencySource::Header => "`//#` header",
+            DependencySource::Frontmatter => "`//# ---` frontmatter",
+            DependencySource::Sidecar => "`.deps` sidecar",
+            DependencySource::ManifestInclude => "--manifest-include",
+            DependencySource::Project => "--from-project",
+            DependencySource::Inferred => "--infer",
+        })
+    }
+}
+
+/// The source a dependency is taken from, and the other sources declaring it as well.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DependencyOrigin {
+    pub(crate) name: String,
+    pub(crate) kind: DependencyKind,
+    pub(crate) source: DependencySource,
+    pub(crate) overridden: Vec<DependencySource>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CargoManifest {
     package: CargoPackage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lib: Option<CargoTarget>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bin: Vec<Table>,
     #[serde(serialize_with = "toml::ser::tables_last")]
     dependencies: Table,
+    #[serde(
+        rename = "dev-dependencies",
+        serialize_with = "toml::ser::tables_last",
+        skip_serializing_if = "Table::is_empty"
+    )]
+    dev_dependencies: Table,
+    #[serde(
+        rename = "build-dependencies",
+        serialize_with = "toml::ser::tables_last",
+        skip_serializing_if = "Table::is_empty"
+    )]
+    build_dependencies: Table,
+    #[serde(skip_serializing_if = "Table::is_empty")]
+    target: Table,
+    #[serde(skip_serializing_if = "Table::is_empty")]
+    profile: Table,
+    #[serde(skip)]
+    origins: Vec<DependencyOrigin>,
 }
 
 impl CargoManifest {
@@ -37,8 +122,112 @@ impl CargoManifest {
         edition: RustEdition,
     ) -> Result<Self, CargoPlayError> {
         let dependencies = dependencies
-            .into_iter()
-            .map(|dependency| dependency.parse::<toml::Value>())
+            .iter()
+            .map(|line| line.parse())
+            .collect::<Result<Vec<Dependency>, _>>()?;
+
+        let mut manifest = Self {
+            package: CargoPackage::new(name, edition),
+            lib: None,
+            bin: Vec::new(),
+            dependencies: Table::new(),
+            dev_dependencies: Table::new(),
+            build_dependencies: Table::new(),
+            target: Self::deserialize_target_deps(&dependencies)?,
+            profile: Table::new(),
+            origins: Vec::new(),
+        };
+
+        for kind in [
+            DependencyKind::Normal,
+            DependencyKind::Dev,
+            DependencyKind::Build,
+        ] {
+            // a crate declared by several header lines is taken from the last one
+            for header in Self::deserialize_deps(&dependencies, kind)? {
+                for (name, spec) in header {
+                    if !manifest.record_origin(&name, kind, DependencySource::Header) {
+                        let previous = manifest.origin(&name, kind).map(|o| o.name.clone());
+                        if let Some(previous) = previous {
+                            manifest.dependencies_mut(kind).remove(&previous);
+                        }
+                    }
+                    manifest.dependencies_mut(kind).insert(name, spec);
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Record `source` as declaring the dependency, returning whether it is the first source
+    /// doing so. Names are compared the way crates.io does, `-` and `_` being the same.
+    fn record_origin(
+        &mut self,
+        name: &str,
+        kind: DependencyKind,
+        source: DependencySource,
+    ) -> bool {
+        let normalized = Self::normalize_crate_name(name);
+
+        match self.origins.iter_mut().find(|origin| {
+            origin.kind == kind && Self::normalize_crate_name(&origin.name) == normalized
+        }) {
+            Some(origin) => {
+                origin.overridden.push(source);
+                false
+            }
+            None => {
+                self.origins.push(DependencyOrigin {
+                    name: name.to_string(),
+                    kind,
+                    source,
+                    overridden: Vec::new(),
+                });
+                true
+            }
+        }
+    }
+
+    /// Fail when there are more than `max` dependencies of any kind, listing the inferred ones.
+    pub(crate) fn check_dependency_count(&self, max: usize) -> Result<(), CargoPlayError> {
+        if self.origins.len() <= max {
+            return Ok(());
+        }
+
+        let inferred: Vec<&str> = self
+            .origins
+            .iter()
+            .filter(|origin| origin.source == DependencySource::Inferred)
+            .map(|origin| origin.name.as_str())
+            .collect();
+        let inferred = if inferred.is_empty() {
+            String::from("none")
+        } else {
+            inferred.join(", ")
+        };
+
+        Err(CargoPlayError::TooManyDependencies(
+            self.origins.len(),
+            max,
+            inferred,
+        ))
+    }
+
+    /// Where each dependency of the manifest comes from, in order of declaration.
+    pub(crate) fn origins(&self) -> &[DependencyOrigin] {
+        &self.origins
+    }
+
+    /// Parse the specs of all dependencies of the given kind, in order.
+    fn deserialize_deps(
+        dependencies: &[Dependency],
+        kind: DependencyKind,
+    ) -> Result<Vec<Table>, CargoPlayError> {
+        let dependencies = dependencies
+            .iter()
+            .filter(|dependency| dependency.kind == kind && dependency.target.is_none())
+            .map(|dependency| dependency.spec.parse::<toml::Value>())
             .collect::<Result<Vec<toml::Value>, _>>()
             .map_err(CargoPlayError::from_serde)?;
 
@@ -46,15 +235,136 @@ impl CargoManifest {
             return Err(CargoPlayError::ParseError("format error!".into()));
         }
 
-        let dependencies: Table = dependencies
+        Ok(dependencies
             .into_iter()
-            .map(|d| d.try_into::<Table>().unwrap().into_iter())
-            .flatten()
+            .map(|d| d.try_into::<Table>().unwrap())
+            .collect())
+    }
+
+    /// Parse the specs of the target specific dependencies into `[target.'cfg(..)'.*]` tables.
+    fn deserialize_target_deps(dependencies: &[Dependency]) -> Result<Table, CargoPlayError> {
+        let mut targets = Table::new();
+
+        for dependency in dependencies {
+            let target = match &dependency.target {
+                Some(target) => target,
+                None => continue,
+            };
+            let section = match dependency.kind {
+                DependencyKind::Normal => "dependencies",
+                DependencyKind::Dev => "dev-dependencies",
+                DependencyKind::Build => "build-dependencies",
+            };
+            let spec = match dependency.spec.parse::<toml::Value>() {
+                Ok(Value::Table(spec)) => spec,
+                Ok(_) => return Err(CargoPlayError::ParseError("format error!".into())),
+                Err(e) => return Err(CargoPlayError::from_serde(e)),
+            };
+
+            let sections = targets
+                .entry(target.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Some(section) = sections
+                .as_table_mut()
+                .map(|sections| {
+                    sections
+                        .entry(section)
+                        .or_insert_with(|| Value::Table(Table::new()))
+                })
+                .and_then(Value::as_table_mut)
+            {
+                section.extend(spec);
+            }
+        }
+
+        Ok(targets)
+    }
+
+    /// Declare a library target with the given crate name, built from `src/lib.rs`.
+    pub(crate) fn set_lib(&mut self, name: String) {
+        self.lib = Some(CargoTarget {
+            name,
+            path: "src/lib.rs".into(),
+        });
+    }
+
+    /// Override the resolver following from the edition, see `--resolver`.
+    pub(crate) fn set_resolver(&mut self, resolver: &str) {
+        self.package.resolver = Some(resolver.into());
+    }
+
+    /// Declare the minimum supported Rust version, from a `//# rust-version: 1.70` header.
+    pub(crate) fn set_rust_version(&mut self, version: &str) {
+        self.package.rust_version = Some(version.into());
+    }
+
+    /// Set the panic strategy of the dev and release profiles. Cargo ignores it for tests.
+    pub(crate) fn set_panic(&mut self, strategy: PanicStrategy) {
+        let settings: Vec<ProfileSetting> = ["dev", "release"]
+            .iter()
+            .map(|profile| ProfileSetting {
+                profile: profile.to_string(),
+                key: "panic".into(),
+                value: Value::String(strategy.as_str().into()),
+            })
             .collect();
 
-        Ok(Self {
-            package: CargoPackage::new(name, edition),
-            dependencies,
+        self.set_profiles(&settings);
+    }
+
+    /// Apply `--profile-set` settings to the `[profile.*]` tables.
+    pub(crate) fn set_profiles(&mut self, settings: &[ProfileSetting]) {
+        for setting in settings {
+            if let Value::Table(profile) = self
+                .profile
+                .entry(setting.profile.clone())
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                profile.insert(setting.key.clone(), setting.value.clone());
+            }
+        }
+    }
+
+    /// Declare additional `[[bin]]` targets.
+    pub(crate) fn add_bins(&mut self, bins: Vec<Table>) {
+        self.bin.extend(bins);
+    }
+
+    fn dependencies_mut(&mut self, kind: DependencyKind) -> &mut Table {
+        match kind {
+            DependencyKind::Normal => &mut self.dependencies,
+            DependencyKind::Dev => &mut self.dev_dependencies,
+            DependencyKind::Build => &mut self.build_dependencies,
+        }
+    }
+
+    /// Merge already parsed dependencies of the given kind from `source`, e.g. borrowed from
+    /// another project. This is the one place dependencies are merged, so sources must be merged
+    /// in order of precedence: dependencies already declared win. The conflicting ones are
+    /// returned, along with the source they are taken from.
+    pub(crate) fn merge_dependencies(
+        &mut self,
+        source: DependencySource,
+        kind: DependencyKind,
+        dependencies: Table,
+    ) -> Vec<(String, DependencySource)> {
+        let mut conflicts = Vec::new();
+
+        for (name, spec) in dependencies {
+            if self.record_origin(&name, kind, source) {
+                self.dependencies_mut(kind).insert(name, spec);
+            } else if let Some(origin) = self.origin(&name, kind) {
+                conflicts.push((name, origin.source));
+            }
+        }
+
+        conflicts
+    }
+
+    fn origin(&self, name: &str, kind: DependencyKind) -> Option<&DependencyOrigin> {
+        let normalized = Self::normalize_crate_name(name);
+        self.origins.iter().find(|origin| {
+            origin.kind == kind && Self::normalize_crate_name(&origin.name) == normalized
         })
     }
 
@@ -62,24 +372,905 @@ impl CargoManifest {
         name.replace("-", "_")
     }
 
-    fn normalized_dependencies(&self) -> HashSet<String> {
-        self.dependencies
-            .clone()
-            .into_iter()
-            .map(|(key, _)| Self::normalize_crate_name(&key))
-            .collect()
+    /// Whether the crate is declared as a dev or build dependency, or for some targets only.
+    fn declared_elsewhere(&self, name: &str) -> bool {
+        let normalized = Self::normalize_crate_name(name);
+        let matches = |declared: &str| Self::normalize_crate_name(declared) == normalized;
+
+        self.origins
+            .iter()
+            .any(|origin| origin.kind != DependencyKind::Normal && matches(&origin.name))
+            || self
+                .target
+                .values()
+                .filter_map(Value::as_table)
+                .flat_map(|sections| sections.values().filter_map(Value::as_table))
+                .any(|section| section.keys().any(|declared| matches(declared)))
+    }
+
+    /// Check the git and path sources of the dependencies, making relative paths absolute from
+    /// `base`: cargo would resolve them from the generated project instead.
+    pub(crate) fn resolve_sources(&mut self, base: &Path) -> Result<(), CargoPlayError> {
+        for dependencies in [
+            &mut self.dependencies,
+            &mut self.dev_dependencies,
+            &mut self.build_dependencies,
+        ] {
+            resolve_sources(dependencies, base)?;
+        }
+
+        for (_, sections) in self.target.iter_mut() {
+            for (_, section) in sections
+                .as_table_mut()
+                .into_iter()
+                .flat_map(|s| s.iter_mut())
+            {
+                if let Some(dependencies) = section.as_table_mut() {
+                    resolve_sources(dependencies, base)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Declare the inferred crates that aren't dependencies yet, under the name and with the
+    /// version requirement given by `requirement`, e.g. `async-std` as spelled by the registry
+    /// for an inferred `async_std`. Inferred crates have the lowest precedence, the spec of a
+    /// crate declared otherwise is kept as it is, features included.
+    pub(crate) fn add_infers<F: Fn(&str) -> (String, String)>(
+        &mut self,
+        infers: HashSet<String>,
+        requirement: F,
+    ) {
+        let mut infers: Vec<String> = infers.into_iter().collect();
+        infers.sort();
+
+        for name in infers {
+            // adding it to [dependencies] would build it without the declared features
+            if self.declared_elsewhere(&name) {
+                continue;
+            }
+            // only crates that aren't declared are looked up
+            if self.origin(&name, DependencyKind::Normal).is_some() {
+                self.record_origin(&name, DependencyKind::Normal, DependencySource::Inferred);
+                continue;
+            }
+
+            let (name, version) = requirement(&name);
+            self.record_origin(&name, DependencyKind::Normal, DependencySource::Inferred);
+            self.dependencies.insert(name, Value::String(version));
+        }
+    }
+
+    /// Serialize the manifest in the given format.
+    pub(crate) fn serialize(&self, format: ManifestFormat) -> Result<Vec<u8>, CargoPlayError> {
+        match format {
+            ManifestFormat::Toml => toml::to_vec(self).map_err(CargoPlayError::from_serde),
+            ManifestFormat::Pretty => self.to_pretty().map(String::into_bytes),
+        }
+    }
+
+    fn to_pretty(&self) -> Result<String, CargoPlayError> {
+        let manifest = match Value::try_from(self).map_err(CargoPlayError::from_serde)? {
+            Value::Table(manifest) => manifest,
+            _ => unreachable!("a manifest serializes to a table"),
+        };
+        // the package keeps its field order, which the table would sort
+        let package = toml::to_string(&self.package).map_err(CargoPlayError::from_serde)?;
+        let mut out = format!("# Generated by cargo-play\n\n[package]\n{}", package);
+
+        for section in SECTIONS {
+            if let Some(value) = manifest.get(*section) {
+                write_pretty_value(&mut out, &[section.to_string()], value);
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Check that the git and path sources of `dependencies` are well formed, and make the relative
+/// paths absolute from `base`. Registry dependencies are left alone.
+pub(crate) fn resolve_sources(dependencies: &mut Table, base: &Path) -> Result<(), CargoPlayError> {
+    for (name, spec) in dependencies.iter_mut() {
+        let spec = match spec.as_table_mut() {
+            Some(spec) => spec,
+            None => continue,
+        };
+        let invalid =
+            |reason: &str| CargoPlayError::InvalidDependencySource(name.clone(), reason.into());
+
+        let references: Vec<&str> = ["branch", "tag", "rev"]
+            .iter()
+            .copied()
+            .filter(|key| spec.contains_key(*key))
+            .collect();
+        match spec.get("git") {
+            Some(Value::String(_)) if references.len() > 1 => {
+                return Err(invalid(
+                    "only one of `branch`, `tag` and `rev` can be given",
+                ))
+            }
+            Some(Value::String(_)) => {}
+            Some(_) => return Err(invalid("`git` must be a URL")),
+            None if !references.is_empty() => {
+                return Err(invalid(&format!("`{}` needs a `git` URL", references[0])))
+            }
+            None => {}
+        }
+
+        match spec.get_mut("path") {
+            Some(Value::String(path)) if Path::new(path).is_relative() => {
+                *path = base.join(&path).to_string_lossy().into_owned();
+            }
+            Some(Value::String(_)) | None => {}
+            Some(_) => return Err(invalid("`path` must be a string")),
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the `[dependencies]` of the project at `dir`. Relative `path` dependencies are made
+/// absolute so they still resolve from the generated project.
+pub(crate) fn read_project_dependencies(dir: &Path) -> Result<Table, CargoPlayError> {
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml"))?;
+    let manifest: Table = toml::from_str(&manifest).map_err(CargoPlayError::from_serde)?;
+
+    let mut dependencies = match manifest.get("dependencies") {
+        Some(Value::Table(dependencies)) => dependencies.clone(),
+        _ => Table::new(),
+    };
+    resolve_sources(&mut dependencies, dir)?;
+
+    Ok(dependencies)
+}
+
+/// Read the dependencies of a `.deps` sidecar file, given either as `//#` header lines or as a
+/// manifest fragment like the one of `--manifest-include`.
+pub(crate) fn read_sidecar(content: &str) -> Result<Vec<(DependencyKind, Table)>, CargoPlayError> {
+    let lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    if !lines
+        .clone()
+        .next()
+        .is_some_and(|line| line.starts_with("//#"))
+    {
+        return read_manifest_include(content);
     }
 
-    pub(crate) fn add_infers(&mut self, infers: HashSet<String>) {
-        let existing = self.normalized_dependencies();
+    let dependencies = lines
+        .filter_map(|line| line.strip_prefix("//#"))
+        .map(|line| line.parse())
+        .collect::<Result<Vec<Dependency>, _>>()?;
+    if dependencies
+        .iter()
+        .any(|dependency| dependency.target.is_some())
+    {
+        return Err(CargoPlayError::ParseError(
+            "target specific dependencies can't be declared in a sidecar".into(),
+        ));
+    }
+
+    [
+        DependencyKind::Normal,
+        DependencyKind::Dev,
+        DependencyKind::Build,
+    ]
+    .iter()
+    .map(|&kind| {
+        let tables = CargoManifest::deserialize_deps(&dependencies, kind)?;
+        Ok((kind, tables.into_iter().flatten().collect()))
+    })
+    .collect()
+}
+
+/// Order of the top-level sections following `[package]` in pretty manifests.
+const SECTIONS: &[&str] = &[
+    "lib",
+    "bin",
+    "dependencies",
+    "dev-dependencies",
+    "build-dependencies",
+    "target",
+    "profile",
+];
 
-        // we don't need to normalize crate name here (in filter) since it's impossible to have
-        // dash in use statments.
-        self.dependencies.extend(
-            infers
+/// A key as written in TOML, quoted unless it is a bare key.
+fn pretty_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if bare {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+/// A value written on a single line, with inline tables listing `version` first.
+fn inline_value(value: &Value) -> String {
+    match value {
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(inline_value).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Value::Table(table) => {
+            let mut entries: Vec<(&String, &Value)> = table.iter().collect();
+            entries.sort_by_key(|(key, _)| *key != "version");
+            let entries: Vec<String> = entries
                 .into_iter()
-                .filter(|key| !existing.contains(key))
-                .map(|key| (key, Value::String("*".into()))),
+                .map(|(key, value)| format!("{} = {}", pretty_key(key), inline_value(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Write the `[path]` section of `table` followed by its sub-sections. Entries of dependency
+/// sections are kept on one line each, like in a hand-written manifest.
+fn write_pretty_section(out: &mut String, path: &[String], table: &Table) {
+    let dependencies = path
+        .last()
+        .is_some_and(|name| name.ends_with("dependencies"));
+    let (entries, sections): (Vec<_>, Vec<_>) = table
+        .iter()
+        .partition(|(_, value)| dependencies || !(value.is_table() || is_array_of_tables(value)));
+    let header: Vec<String> = path.iter().map(|key| pretty_key(key)).collect();
+
+    if !entries.is_empty() || sections.is_empty() {
+        out.push_str(&format!("\n[{}]\n", header.join(".")));
+        for (key, value) in entries {
+            out.push_str(&format!("{} = {}\n", pretty_key(key), inline_value(value)));
+        }
+    }
+
+    for (key, value) in sections {
+        let mut path = path.to_vec();
+        path.push(key.clone());
+        write_pretty_value(out, &path, value);
+    }
+}
+
+fn is_array_of_tables(value: &Value) -> bool {
+    value
+        .as_array()
+        .is_some_and(|values| !values.is_empty() && values.iter().all(Value::is_table))
+}
+
+fn write_pretty_value(out: &mut String, path: &[String], value: &Value) {
+    match value {
+        Value::Table(table) => write_pretty_section(out, path, table),
+        Value::Array(tables) => {
+            let header: Vec<String> = path.iter().map(|key| pretty_key(key)).collect();
+            for table in tables.iter().filter_map(Value::as_table) {
+                out.push_str(&format!("\n[[{}]]\n", header.join(".")));
+                for (key, value) in table {
+                    out.push_str(&format!("{} = {}\n", pretty_key(key), inline_value(value)));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Read the edition of the package in `dir`, which is 2015 when the manifest does not set it.
+pub(crate) fn read_project_edition(dir: &Path) -> Result<RustEdition, CargoPlayError> {
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml"))?;
+    let manifest: Table = toml::from_str(&manifest).map_err(CargoPlayError::from_serde)?;
+
+    match manifest
+        .get("package")
+        .and_then(|package| package.get("edition"))
+    {
+        Some(Value::String(edition)) => edition.parse(),
+        _ => Ok(RustEdition::E2015),
+    }
+}
+
+/// The dependency sections of a manifest fragment, by kind.
+pub(crate) type DependencySections = Vec<(DependencyKind, Table)>;
+
+/// Read the dependency sections of a manifest fragment given with `--manifest-include`. Only the
+/// `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]` sections are allowed.
+pub(crate) fn read_manifest_include(content: &str) -> Result<DependencySections, CargoPlayError> {
+    let fragment: Table = toml::from_str(content).map_err(CargoPlayError::from_serde)?;
+
+    dependency_sections(fragment, "manifest include")
+}
+
+/// Parse the `//# ---` frontmatter of a source, a manifest fragment with dependency sections
+/// like a manifest include, and `[profile.*]` settings.
+pub(crate) fn read_frontmatter(
+    content: &str,
+) -> Result<(DependencySections, Vec<ProfileSetting>), CargoPlayError> {
+    let mut fragment: Table = toml::from_str(content).map_err(CargoPlayError::from_serde)?;
+    let mut profiles = Vec::new();
+
+    match fragment.remove("profile") {
+        Some(Value::Table(tables)) => {
+            for (profile, settings) in tables {
+                let settings = match settings {
+                    Value::Table(settings) => settings,
+                    _ => {
+                        return Err(CargoPlayError::ParseError(format!(
+                            "[profile.{}] must be a table",
+                            profile
+                        )))
+                    }
+                };
+                profiles.extend(settings.into_iter().map(|(key, value)| ProfileSetting {
+                    profile: profile.clone(),
+                    key,
+                    value,
+                }));
+            }
+        }
+        Some(_) => {
+            return Err(CargoPlayError::ParseError(
+                "[profile] must be a table".into(),
+            ))
+        }
+        None => {}
+    }
+
+    Ok((dependency_sections(fragment, "frontmatter")?, profiles))
+}
+
+/// The dependency tables of a manifest fragment read from `origin`, which can't have other
+/// sections.
+fn dependency_sections(
+    fragment: Table,
+    origin: &str,
+) -> Result<Vec<(DependencyKind, Table)>, CargoPlayError> {
+    fragment
+        .into_iter()
+        .map(|(section, value)| {
+            let kind = match section.as_str() {
+                "dependencies" => DependencyKind::Normal,
+                "dev-dependencies" => DependencyKind::Dev,
+                "build-dependencies" => DependencyKind::Build,
+                _ => {
+                    return Err(CargoPlayError::ParseError(format!(
+                        "unsupported section [{}] in {}",
+                        section, origin
+                    )))
+                }
+            };
+
+            match value {
+                Value::Table(dependencies) => Ok((kind, dependencies)),
+                _ => Err(CargoPlayError::ParseError(format!(
+                    "[{}] must be a table",
+                    section
+                ))),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opt::VersionStyle;
+
+    fn manifest(dependencies: &[&str]) -> CargoManifest {
+        let dependencies = dependencies.iter().map(|d| d.to_string()).collect();
+        CargoManifest::new("test".into(), dependencies, RustEdition::default()).unwrap()
+    }
+
+    #[test]
+    fn test_merge_dependencies() {
+        let mut manifest = manifest(&[r#"serde = "1.0.100""#]);
+        let project: Table = toml::from_str(
+            r#"
+            serde = "1"
+            log = "0.4"
+            "#,
+        )
+        .unwrap();
+
+        let conflicts =
+            manifest.merge_dependencies(DependencySource::Project, DependencyKind::Normal, project);
+
+        assert_eq!(
+            conflicts,
+            vec![(String::from("serde"), DependencySource::Header)]
+        );
+        assert_eq!(manifest.dependencies["serde"].as_str(), Some("1.0.100"));
+        assert_eq!(manifest.dependencies["log"].as_str(), Some("0.4"));
+    }
+
+    #[test]
+    fn test_renamed_dependency() {
+        let manifest = manifest(&[r#"myalias = { package = "original-crate", version = "1" }"#]);
+        let serialized = toml::to_string(&manifest).unwrap();
+        let parsed: toml::Value = toml::from_str(&serialized).unwrap();
+        let dependency = &parsed["dependencies"]["myalias"];
+
+        assert_eq!(dependency["package"].as_str(), Some("original-crate"));
+        assert_eq!(dependency["version"].as_str(), Some("1"));
+        assert!(parsed["dependencies"].get("original-crate").is_none());
+    }
+
+    #[test]
+    fn test_set_panic() {
+        let mut manifest = manifest(&[]);
+        manifest.set_panic(PanicStrategy::Abort);
+        manifest.set_profiles(&["release.panic=\"unwind\"".parse().unwrap()]);
+
+        assert_eq!(manifest.profile["dev"]["panic"].as_str(), Some("abort"));
+        // explicit settings take precedence
+        assert_eq!(
+            manifest.profile["release"]["panic"].as_str(),
+            Some("unwind")
+        );
+    }
+
+    #[test]
+    fn test_editions() {
+        for (name, edition) in &[
+            ("2015", RustEdition::E2015),
+            ("2018", RustEdition::E2018),
+            ("2021", RustEdition::E2021),
+            ("2024", RustEdition::E2024),
+        ] {
+            assert_eq!(name.parse::<RustEdition>().unwrap(), *edition);
+
+            let manifest = CargoManifest::new("test".into(), Vec::new(), *edition).unwrap();
+            let parsed: toml::Value = toml::from_str(&toml::to_string(&manifest).unwrap()).unwrap();
+            assert_eq!(parsed["package"]["edition"].as_str(), Some(*name));
+        }
+
+        assert!("2027".parse::<RustEdition>().is_err());
+        assert_eq!(RustEdition::default(), RustEdition::E2021);
+    }
+
+    #[test]
+    fn test_resolver() {
+        for edition in &[RustEdition::E2015, RustEdition::E2018] {
+            let manifest = CargoManifest::new("test".into(), Vec::new(), *edition).unwrap();
+            let serialized = toml::to_string(&manifest).unwrap();
+            // cargo defaults to resolver "1" for these editions
+            assert!(!serialized.contains("resolver"));
+        }
+
+        for (edition, resolver) in &[(RustEdition::E2021, "2"), (RustEdition::E2024, "3")] {
+            let manifest = CargoManifest::new("test".into(), Vec::new(), *edition).unwrap();
+            let parsed: toml::Value = toml::from_str(&toml::to_string(&manifest).unwrap()).unwrap();
+            assert_eq!(parsed["package"]["resolver"].as_str(), Some(*resolver));
+        }
+
+        let mut manifest = manifest(&[]);
+        manifest.set_resolver("2");
+        let parsed: toml::Value = toml::from_str(&toml::to_string(&manifest).unwrap()).unwrap();
+        assert_eq!(parsed["package"]["resolver"].as_str(), Some("2"));
+    }
+
+    #[test]
+    fn test_rust_version() {
+        let mut manifest = manifest(&[]);
+        assert!(!toml::to_string(&manifest).unwrap().contains("rust-version"));
+
+        manifest.set_rust_version("1.70");
+        let parsed: toml::Value = toml::from_str(&toml::to_string(&manifest).unwrap()).unwrap();
+        assert_eq!(parsed["package"]["rust-version"].as_str(), Some("1.70"));
+    }
+
+    #[test]
+    fn test_manifest_formats() {
+        let mut manifest = manifest(&[
+            r#"serde = { version = "1", features = ["derive"] }"#,
+            r#"log = "0.4""#,
+            r#"dev: criterion = "0.3""#,
+            r#"cfg(unix): libc = "0.2""#,
+        ]);
+        manifest.set_profiles(&["release.lto=true".parse().unwrap()]);
+
+        let plain = String::from_utf8(manifest.serialize(ManifestFormat::Toml).unwrap()).unwrap();
+        let pretty =
+            String::from_utf8(manifest.serialize(ManifestFormat::Pretty).unwrap()).unwrap();
+
+        assert!(plain.contains("[dependencies.serde]\nfeatures = [\"derive\"]\nversion = \"1\"\n"));
+        assert_eq!(
+            pretty,
+            r#"# Generated by cargo-play
+
+[package]
+name = "test"
+version = "0.1.0"
+edition = "2021"
+resolver = "2"
+
+[dependencies]
+log = "0.4"
+serde = { version = "1", features = ["derive"] }
+
+[dev-dependencies]
+criterion = "0.3"
+
+[target."cfg(unix)".dependencies]
+libc = "0.2"
+
+[profile.release]
+lto = true
+"#
+        );
+        assert_eq!(
+            toml::from_str::<Value>(&plain).unwrap(),
+            toml::from_str::<Value>(&pretty).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merge_manifest_include() {
+        let mut manifest = manifest(&[r#"serde = "1""#, r#"dev: rand = "0.7""#]);
+        let sections = read_manifest_include(
+            r#"
+            [dependencies]
+            serde = "1.0.100"
+
+            [dependencies.tokio]
+            version = "0.2"
+            features = [
+                "macros",
+                "rt-threaded",
+            ]
+
+            [dev-dependencies]
+            rand = "0.6"
+            quickcheck = "0.9"
+            "#,
+        )
+        .unwrap();
+
+        let conflicts: Vec<String> = sections
+            .into_iter()
+            .flat_map(|(kind, dependencies)| {
+                manifest.merge_dependencies(DependencySource::ManifestInclude, kind, dependencies)
+            })
+            .map(|(name, _)| name)
+            .collect();
+
+        assert_eq!(conflicts, vec![String::from("serde"), String::from("rand")]);
+        assert_eq!(manifest.dependencies["serde"].as_str(), Some("1"));
+        assert_eq!(
+            manifest.dependencies["tokio"]["features"]
+                .as_array()
+                .map(Vec::len),
+            Some(2)
+        );
+        assert_eq!(manifest.dev_dependencies["rand"].as_str(), Some("0.7"));
+        assert_eq!(
+            manifest.dev_dependencies["quickcheck"].as_str(),
+            Some("0.9")
+        );
+
+        assert!(read_manifest_include("[package]\nname = \"x\"").is_err());
+        assert!(read_manifest_include("dependencies = 1").is_err());
+    }
+
+    #[test]
+    fn test_check_dependency_count() {
+        let mut manifest = manifest(&[r#"serde = "1""#, r#"dev: rand = "0.7""#]);
+        manifest.add_infers(
+            ["regex", "serde"].iter().map(|s| s.to_string()).collect(),
+            |name| (name.into(), "*".into()),
+        );
+
+        assert!(manifest.check_dependency_count(3).is_ok());
+        assert_eq!(
+            manifest.check_dependency_count(2).unwrap_err().to_string(),
+            "3 dependencies exceed --max-deps 2, inferred: regex"
+        );
+    }
+
+    #[test]
+    fn test_read_sidecar() {
+        let headers = read_sidecar("//# serde = \"1\"\n\n//# dev: rand = \"0.7\"\n").unwrap();
+        let fragment = read_sidecar("[dependencies]\nserde = \"1\"\n").unwrap();
+
+        assert_eq!(headers[0].0, DependencyKind::Normal);
+        assert_eq!(headers[0].1["serde"].as_str(), Some("1"));
+        assert_eq!(headers[1].0, DependencyKind::Dev);
+        assert_eq!(headers[1].1["rand"].as_str(), Some("0.7"));
+        assert!(headers[2].1.is_empty());
+        assert_eq!(
+            fragment,
+            vec![(DependencyKind::Normal, headers[0].1.clone())]
+        );
+
+        assert!(read_sidecar("//# cfg(unix): libc = \"0.2\"").is_err());
+    }
+
+    #[test]
+    fn test_dependency_precedence() {
+        let mut manifest = manifest(&[r#"serde = "1""#, r#"serde = "1.0.100""#]);
+        let include: Table = toml::from_str("serde_json = \"1\"\nlog = \"0.4\"").unwrap();
+        let project: Table = toml::from_str("serde-json = \"0.9\"\nrand = \"0.7\"").unwrap();
+
+        let conflicts = manifest.merge_dependencies(
+            DependencySource::ManifestInclude,
+            DependencyKind::Normal,
+            include,
+        );
+        assert!(conflicts.is_empty());
+        let conflicts =
+            manifest.merge_dependencies(DependencySource::Project, DependencyKind::Normal, project);
+        assert_eq!(
+            conflicts,
+            vec![(
+                String::from("serde-json"),
+                DependencySource::ManifestInclude
+            )]
+        );
+        manifest.add_infers(
+            ["serde", "rand", "regex"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            |name| (name.into(), "*".into()),
+        );
+
+        assert_eq!(manifest.dependencies["serde"].as_str(), Some("1.0.100"));
+        assert_eq!(manifest.dependencies["serde_json"].as_str(), Some("1"));
+        assert!(!manifest.dependencies.contains_key("serde-json"));
+        assert_eq!(manifest.dependencies["rand"].as_str(), Some("0.7"));
+        assert_eq!(manifest.dependencies["regex"].as_str(), Some("*"));
+
+        let origins: Vec<(&str, DependencySource, &[DependencySource])> = manifest
+            .origins()
+            .iter()
+            .map(|o| (o.name.as_str(), o.source, o.overridden.as_slice()))
+            .collect();
+        assert_eq!(
+            origins,
+            vec![
+                (
+                    "serde",
+                    DependencySource::Header,
+                    &[DependencySource::Header, DependencySource::Inferred][..]
+                ),
+                ("log", DependencySource::ManifestInclude, &[][..]),
+                (
+                    "serde_json",
+                    DependencySource::ManifestInclude,
+                    &[DependencySource::Project][..]
+                ),
+                (
+                    "rand",
+                    DependencySource::Project,
+                    &[DependencySource::Inferred][..]
+                ),
+                ("regex", DependencySource::Inferred, &[][..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_profiles() {
+        let mut manifest = manifest(&[]);
+        let settings: Vec<ProfileSetting> =
+            ["dev.opt-level=1", "dev.debug=false", "release.lto=true"]
+                .iter()
+                .map(|setting| setting.parse().unwrap())
+                .collect();
+
+        manifest.set_profiles(&settings);
+
+        let serialized = toml::to_string(&manifest).unwrap();
+        let parsed: toml::Value = toml::from_str(&serialized).unwrap();
+        assert_eq!(parsed["profile"]["dev"]["opt-level"].as_integer(), Some(1));
+        assert_eq!(parsed["profile"]["dev"]["debug"].as_bool(), Some(false));
+        assert_eq!(parsed["profile"]["release"]["lto"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_target_dependencies() {
+        let manifest = manifest(&[
+            r#"serde = "1""#,
+            r#"cfg(unix): nix = "0.27""#,
+            r#"dev: rand = "0.7""#,
+            r#"dev: cfg(unix): tempfile = "3""#,
+            r#"build: cc = "1""#,
+            r#"build: cfg(windows): winres = { version = "0.1" }"#,
+        ]);
+        let serialized = toml::to_string(&manifest).unwrap();
+        let parsed: toml::Value = toml::from_str(&serialized).unwrap();
+        let unix = &parsed["target"]["cfg(unix)"];
+        let windows = &parsed["target"]["cfg(windows)"];
+
+        assert_eq!(parsed["dependencies"]["serde"].as_str(), Some("1"));
+        assert_eq!(unix["dependencies"]["nix"].as_str(), Some("0.27"));
+        assert_eq!(parsed["dev-dependencies"]["rand"].as_str(), Some("0.7"));
+        assert_eq!(unix["dev-dependencies"]["tempfile"].as_str(), Some("3"));
+        assert_eq!(parsed["build-dependencies"]["cc"].as_str(), Some("1"));
+        assert_eq!(
+            windows["build-dependencies"]["winres"]["version"].as_str(),
+            Some("0.1")
+        );
+        assert!(parsed["dependencies"].get("nix").is_none());
+    }
+
+    #[test]
+    fn test_resolve_sources() {
+        let mut resolved = manifest(&[
+            r#"local = { path = "../local" }"#,
+            r#"fixed = { path = "/opt/fixed" }"#,
+            r#"remote = { git = "https://example.com/remote.git", branch = "main" }"#,
+            r#"dev: helper = { path = "helper" }"#,
+            r#"cfg(unix): unix-only = { path = "unix" }"#,
+            r#"serde = "1""#,
+        ]);
+        resolved
+            .resolve_sources(Path::new("/src/snippets"))
+            .unwrap();
+
+        let parsed: toml::Value = toml::from_str(&toml::to_string(&resolved).unwrap()).unwrap();
+        let path = |section: &toml::Value, name: &str| {
+            section[name]["path"]
+                .as_str()
+                .map(Path::new)
+                .map(Path::to_path_buf)
+        };
+        assert_eq!(
+            path(&parsed["dependencies"], "local"),
+            Some(Path::new("/src/snippets").join("../local"))
+        );
+        assert_eq!(
+            path(&parsed["dependencies"], "fixed"),
+            Some("/opt/fixed".into())
+        );
+        assert_eq!(
+            parsed["dependencies"]["remote"]["branch"].as_str(),
+            Some("main")
         );
+        assert_eq!(
+            path(&parsed["dev-dependencies"], "helper"),
+            Some(Path::new("/src/snippets").join("helper"))
+        );
+        assert_eq!(
+            path(&parsed["target"]["cfg(unix)"]["dependencies"], "unix-only"),
+            Some(Path::new("/src/snippets").join("unix"))
+        );
+        assert_eq!(parsed["dependencies"]["serde"].as_str(), Some("1"));
+
+        for spec in &[
+            r#"remote = { git = "https://example.com/remote.git", branch = "main", tag = "v1" }"#,
+            r#"remote = { version = "1", rev = "abc123" }"#,
+            r#"remote = { git = true }"#,
+            r#"local = { path = 1 }"#,
+        ] {
+            let error = manifest(&[spec])
+                .resolve_sources(Path::new("/src"))
+                .unwrap_err();
+            assert!(matches!(error, CargoPlayError::InvalidDependencySource(..)));
+        }
+    }
+
+    #[test]
+    fn test_read_frontmatter() {
+        let (sections, profiles) = read_frontmatter(
+            r#"
+[dependencies]
+serde = { version = "1", features = ["derive"] }
+log = "0.4"
+
+[dev-dependencies]
+criterion = "0.5"
+
+[build-dependencies]
+cc = "1"
+
+[profile.release]
+lto = true
+"#,
+        )
+        .unwrap();
+
+        let mut manifest = manifest(&[r#"log = "0.3""#]);
+        for (kind, dependencies) in sections {
+            manifest.merge_dependencies(DependencySource::Frontmatter, kind, dependencies);
+        }
+        manifest.set_profiles(&profiles);
+
+        let parsed: toml::Value = toml::from_str(&toml::to_string(&manifest).unwrap()).unwrap();
+        assert_eq!(
+            parsed["dependencies"]["serde"]["features"][0].as_str(),
+            Some("derive")
+        );
+        // `//#` headers take precedence
+        assert_eq!(parsed["dependencies"]["log"].as_str(), Some("0.3"));
+        assert_eq!(
+            parsed["dev-dependencies"]["criterion"].as_str(),
+            Some("0.5")
+        );
+        assert_eq!(parsed["build-dependencies"]["cc"].as_str(), Some("1"));
+        assert_eq!(parsed["profile"]["release"]["lto"].as_bool(), Some(true));
+
+        assert!(read_frontmatter("").unwrap().0.is_empty());
+        assert!(read_frontmatter("[package]\nname = \"other\"").is_err());
+        assert!(read_frontmatter("profile = 1").is_err());
+    }
+
+    #[test]
+    fn test_infers_keep_declared_features() {
+        let infers: HashSet<String> = ["tokio", "async_std", "nix", "criterion"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut manifest = manifest(&[
+            r#"tokio = { version = "1", features = ["full"] }"#,
+            r#"async-std = { version = "1", features = ["attributes"] }"#,
+            r#"cfg(unix): nix = { version = "0.27", features = ["fs"] }"#,
+            r#"dev: criterion = { version = "0.5", features = ["html_reports"] }"#,
+        ]);
+        manifest.add_infers(infers, |name| (name.into(), "*".into()));
+
+        let parsed: toml::Value = toml::from_str(&toml::to_string(&manifest).unwrap()).unwrap();
+        let dependencies = parsed["dependencies"].as_table().unwrap();
+        assert_eq!(
+            dependencies.keys().collect::<Vec<_>>(),
+            vec!["async-std", "tokio"]
+        );
+        assert_eq!(
+            dependencies["tokio"]["features"].as_array().unwrap(),
+            &vec![Value::String("full".into())]
+        );
+        assert_eq!(
+            dependencies["async-std"]["features"].as_array().unwrap(),
+            &vec![Value::String("attributes".into())]
+        );
+        assert_eq!(
+            parsed["target"]["cfg(unix)"]["dependencies"]["nix"]["features"][0].as_str(),
+            Some("fs")
+        );
+        assert_eq!(
+            parsed["dev-dependencies"]["criterion"]["features"][0].as_str(),
+            Some("html_reports")
+        );
+    }
+
+    #[test]
+    fn test_infer_version_styles() {
+        let infers: HashSet<String> = ["serde", "rand"].iter().map(|s| s.to_string()).collect();
+
+        for (style, expected) in &[
+            (VersionStyle::Wildcard, "*"),
+            (VersionStyle::Caret, "^1.0"),
+            (VersionStyle::Exact, "=1.0.104"),
+        ] {
+            let mut manifest = manifest(&[r#"rand = "0.7""#]);
+            manifest.add_infers(infers.clone(), |name| {
+                (name.into(), style.requirement(Some("1.0.104")))
+            });
+
+            assert_eq!(manifest.dependencies["serde"].as_str(), Some(*expected));
+            assert_eq!(manifest.dependencies["rand"].as_str(), Some("0.7"));
+        }
+    }
+
+    #[test]
+    fn test_infers_registry_name() {
+        let infers: HashSet<String> = ["async_std", "serde_json"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut manifest = manifest(&[r#"serde-json = "1""#]);
+        manifest.add_infers(infers, |name| {
+            assert_eq!(name, "async_std", "declared crates aren't looked up");
+            ("async-std".into(), "^1.12".into())
+        });
+
+        assert_eq!(manifest.dependencies["async-std"].as_str(), Some("^1.12"));
+        assert!(!manifest.dependencies.contains_key("async_std"));
+        assert_eq!(manifest.dependencies["serde-json"].as_str(), Some("1"));
+        assert_eq!(manifest.origins().len(), 2);
     }
 }
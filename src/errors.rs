@@ -1,3 +1,5 @@
+#![allow(non_local_definitions)]
+
 use failure::Fail;
 use std::fmt::Debug;
 
@@ -9,18 +11,91 @@ pub enum CargoPlayError {
     #[fail(display = "Parsing error: {:?}", _0)]
     ParseError(String),
 
+    #[fail(
+        display = "Invalid dependency header {}, expected `name = spec` such as `serde = \"1\"`",
+        _0
+    )]
+    InvalidDependency(String),
+
+    #[fail(display = "Invalid source of dependency `{}`: {}", _0, _1)]
+    InvalidDependencySource(String, String),
+
     #[fail(display = "Unable to compute relative path of {:?}", _0)]
     DiffPathError(std::path::PathBuf),
 
-    #[fail(display = "Unexpected edition {:?}. Edition must be 2015/2018.", _0)]
+    #[fail(
+        display = "Unexpected edition {:?}. Edition must be 2015/2018/2021/2024.",
+        _0
+    )]
     InvalidEdition(String),
 
+    #[fail(
+        display = "{:?} is outside the directory of the first source file, pass a file from a parent directory first",
+        _0
+    )]
+    SourceOutsideProject(std::path::PathBuf),
+
     #[fail(display = "Path already exists at {:?}", _0)]
     PathExistError(std::path::PathBuf),
 
     #[fail(display = "Failed to parse source code: {:?}", _0)]
     RustParseError(syn::Error),
 
+    #[fail(display = "Cannot use {:?} as the entry point: {}", _0, _1)]
+    InvalidMainFn(String, String),
+
+    #[fail(
+        display = "Cargo subcommand {:?} not found, install it with `cargo install {}`",
+        _0, _0
+    )]
+    SubcommandNotFound(String),
+
+    #[fail(display = "Failed to build the library artifact")]
+    LibraryBuildError,
+
+    #[fail(display = "{} failed: {}", _0, _1)]
+    CommandFailed(String, std::process::ExitStatus),
+
+    #[fail(
+        display = "`async fn main` needs a runtime, add e.g. `//# tokio = {{ version = \"1\", features = [\"full\"] }}` or `//# async-std = {{ version = \"1\", features = [\"attributes\"] }}`"
+    )]
+    NoAsyncRuntime,
+
+    #[fail(display = "{:?} is not valid UTF-8, re-encode it as UTF-8", _0)]
+    NonUtf8Source(std::path::PathBuf),
+
+    #[fail(
+        display = "No debugger found, install gdb or lldb; rustup provides the rust-gdb and rust-lldb wrappers"
+    )]
+    NoDebugger,
+
+    #[fail(display = "rustup is needed to {}, see https://rustup.rs", _0)]
+    NoRustup(String),
+
+    #[fail(
+        display = "{} dependencies exceed --max-deps {}, inferred: {}",
+        _0, _1, _2
+    )]
+    TooManyDependencies(usize, usize, String),
+
+    #[fail(display = "Macros without a provider:\n{}", _0)]
+    MissingMacroProviders(String),
+
+    #[fail(display = "Build failed")]
+    BuildFailed(Option<i32>),
+
+    #[fail(display = "No container runtime found, install docker or podman")]
+    NoContainerRuntime,
+
+    #[fail(
+        display = "The source needs rust-version {}, but the active toolchain is rustc {}",
+        _0, _1
+    )]
+    RustVersionTooOld(String, String),
+
+    #[fail(display = "Crate {:?} was not found on the registry", _0)]
+    CrateNotFound(String),
+
     /// Helper error kind only exists for development purpose.
     #[fail(display = "{:?}", _0)]
     _Message(String),
@@ -43,6 +118,16 @@ impl CargoPlayError {
         CargoPlayError::ParseError(format!("{:?}", value))
     }
 
+    /// Name of the variant, e.g. `IOError`, identifying the kind of error in JSON reports.
+    pub fn kind(&self) -> String {
+        let debug = format!("{:?}", self);
+        debug
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
     pub fn _message<T: Into<String>>(value: T) -> Self {
         CargoPlayError::_Message(value.into())
     }
@@ -0,0 +1,182 @@
+//! Hints for common build failures of snippets, see `--explain-error`.
+
+use crate::opt::RustEdition;
+
+/// Diagnostics of syntax that older editions reject, e.g. `async fn` (E0670) or `.await`, which
+/// Rust 2015 reads as a field access.
+const EDITION_ERRORS: &[&str] = &[
+    "E0670",
+    "Rust 2015",
+    "edition 2015",
+    "only allowed in Rust 2018 or later",
+    "no field `await`",
+];
+
+/// The name quoted right after `prefix` in `line`, up to the first path separator.
+fn quoted_after<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = &line[line.find(prefix)? + prefix.len()..];
+    let name = &rest[..rest.find('`')?];
+
+    name.split("::").next().filter(|name| !name.is_empty())
+}
+
+fn unresolved_crate(line: &str) -> Option<&str> {
+    [
+        "unresolved import `",
+        "use of undeclared crate or module `",
+        "use of unresolved module or unlinked crate `",
+        "can't find crate for `",
+    ]
+    .iter()
+    .find_map(|prefix| quoted_after(line, prefix))
+    .filter(|name| !["crate", "self", "super", "std", "core", "alloc"].contains(name))
+}
+
+/// The crate, its version and the rustc version it requires, from either of
+/// "package `foo v1.2.3` cannot be built because it requires rustc 1.70 or newer" or
+/// "foo@1.2.3 requires rustc 1.70".
+fn rustc_requirement(line: &str) -> Option<(&str, &str, &str)> {
+    let (package, rest) = if let Some(package) = quoted_after(line, "package `") {
+        (package, line.split_once("requires rustc ")?.1)
+    } else {
+        let (package, rest) = line.trim().split_once(" requires rustc ")?;
+        (package, rest)
+    };
+    let (name, version) = package
+        .split_once(" v")
+        .or_else(|| package.split_once('@'))?;
+    let required = rest.split_whitespace().next()?;
+
+    Some((name, version, required))
+}
+
+/// The text of diagnostics printed in colour, without the terminal escape sequences.
+fn strip_colors(diagnostics: &str) -> String {
+    let mut text = String::with_capacity(diagnostics.len());
+    let mut chars = diagnostics.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ parameters final-byte`
+            chars
+                .by_ref()
+                .find(|c| c.is_ascii_alphabetic() && *c != '[');
+        } else {
+            text.push(c);
+        }
+    }
+
+    text
+}
+
+/// Hints explaining the diagnostics printed by a failed cargo invocation, without duplicates.
+/// Edition upgrades are only suggested when the project was built with an older `edition`.
+pub fn hints(diagnostics: &str, edition: RustEdition) -> Vec<String> {
+    let mut hints: Vec<String> = Vec::new();
+
+    for line in strip_colors(diagnostics).lines() {
+        let hint = if line.contains("E0601") {
+            Some("no `main` function was found; did you mean to pass --lib?".to_string())
+        } else if let Some(name) = quoted_after(line, "no matching package named `") {
+            Some(format!(
+                "there is no crate named `{}` on crates.io; check its `//#` header or, if it was \
+                 inferred, declare the right crate with `//#`",
+                name
+            ))
+        } else if let Some(name) = unresolved_crate(line) {
+            Some(format!(
+                "unresolved crate `{}`; --infer may have guessed wrong, try declaring it with \
+                 `//# {} = \"*\"`",
+                name, name
+            ))
+        } else if let Some((name, version, required)) = rustc_requirement(line) {
+            Some(format!(
+                "`{}` {} needs rustc {}; pin an older version with `//# {} = \"<{}\"` or update \
+                 the toolchain with `rustup update`",
+                name, version, required, name, version
+            ))
+        } else if line.contains("feature `edition20") {
+            Some(
+                "a dependency uses an edition this cargo doesn't know yet; pin an older version \
+                 of it or update the toolchain with `rustup update`"
+                    .to_string(),
+            )
+        } else if edition == RustEdition::E2015
+            && EDITION_ERRORS.iter().any(|error| line.contains(error))
+        {
+            Some(format!(
+                "this code needs a newer edition than {}; try --edition {}",
+                String::from(edition),
+                String::from(RustEdition::default())
+            ))
+        } else {
+            None
+        };
+
+        if let Some(hint) = hint {
+            if !hints.contains(&hint) {
+                hints.push(hint);
+            }
+        }
+    }
+
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hints() {
+        let diagnostics = r#"
+error[E0601]: `main` function not found in crate `abc`
+error[E0432]: unresolved import `serde_jsn`
+error[E0433]: failed to resolve: use of undeclared crate or module `serde_jsn`
+error[E0432]: unresolved import `crate::missing`
+error: no matching package named `not-a-crate` found
+error[E0670]: `async fn` is not permitted in Rust 2015
+"#;
+
+        assert_eq!(
+            hints(diagnostics, RustEdition::E2015),
+            vec![
+                "no `main` function was found; did you mean to pass --lib?",
+                "unresolved crate `serde_jsn`; --infer may have guessed wrong, try declaring it \
+                 with `//# serde_jsn = \"*\"`",
+                "there is no crate named `not-a-crate` on crates.io; check its `//#` header or, \
+                 if it was inferred, declare the right crate with `//#`",
+                "this code needs a newer edition than 2015; try --edition 2021",
+            ]
+        );
+        assert!(hints("error: could not compile `abc`", RustEdition::E2015).is_empty());
+        assert_eq!(
+            hints(
+                "\x1b[0m\x1b[1m\x1b[38;5;9merror[E0601]\x1b[0m\x1b[0m\x1b[1m: `main` function not found",
+                RustEdition::E2018
+            ),
+            ["no `main` function was found; did you mean to pass --lib?"]
+        );
+
+        let msrv = "error: package `rayon-core v1.12.1` cannot be built because it requires \
+                    rustc 1.63 or newer, while the currently active rustc version is 1.60.0";
+        let msrv_hint = "`rayon-core` 1.12.1 needs rustc 1.63; pin an older version with \
+                         `//# rayon-core = \"<1.12.1\"` or update the toolchain with `rustup update`";
+        assert_eq!(hints(msrv, RustEdition::E2018), vec![msrv_hint]);
+        assert_eq!(
+            hints(
+                "  rayon-core@1.12.1 requires rustc 1.63",
+                RustEdition::E2018
+            ),
+            vec![msrv_hint]
+        );
+        assert_eq!(
+            hints("  feature `edition2021` is required", RustEdition::E2018).len(),
+            1
+        );
+
+        let await_field = "error[E0609]: no field `await` on type `impl Future`";
+        assert_eq!(hints(await_field, RustEdition::E2015).len(), 1);
+        assert!(hints(await_field, RustEdition::E2018).is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+//! Files referenced by `include!`, `include_str!` and `include_bytes!`, which have to be copied
+//! into the generated project along with the sources.
+
+use proc_macro2::{TokenStream, TokenTree};
+
+const INCLUDE_MACROS: &[&str] = &["include", "include_str", "include_bytes"];
+
+/// An invocation of one of the include macros.
+#[derive(Debug, PartialEq)]
+pub struct Include {
+    pub macro_name: String,
+    /// The path, when it is given as a string literal.
+    pub path: Option<String>,
+}
+
+fn scan(input: TokenStream, found: &mut Vec<Include>) {
+    use TokenTree as tt;
+
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            tt::Ident(name) if INCLUDE_MACROS.iter().any(|m| name == m) => {
+                if let (Some(tt::Punct(bang)), Some(tt::Group(arguments))) =
+                    (tokens.get(index + 1), tokens.get(index + 2))
+                {
+                    if bang.as_char() == '!' {
+                        found.push(Include {
+                            macro_name: name.to_string(),
+                            path: syn::parse2::<syn::LitStr>(arguments.stream())
+                                .ok()
+                                .map(|path| path.value()),
+                        });
+                    }
+                }
+            }
+            tt::Group(group) => scan(group.stream(), found),
+            _ => {}
+        }
+    }
+}
+
+/// The include macro invocations in a source file, in order of appearance. Sources that can't be
+/// tokenized have none, rustc reports the problem instead.
+pub fn includes(source: &str) -> Vec<Include> {
+    let mut found = Vec::new();
+
+    if let Ok(tokens) = source.parse::<TokenStream>() {
+        scan(tokens, &mut found);
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes() {
+        let source = r#"
+const DATA: &str = include_str!("../data.txt");
+
+fn main() {
+    let bytes = std::include_bytes!(r"logo.png");
+    include!(concat!(env!("OUT_DIR"), "/generated.rs"));
+    println!("{} {}", DATA, bytes.len());
+}
+"#;
+
+        assert_eq!(
+            includes(source),
+            vec![
+                Include {
+                    macro_name: "include_str".into(),
+                    path: Some("../data.txt".into()),
+                },
+                Include {
+                    macro_name: "include_bytes".into(),
+                    path: Some("logo.png".into()),
+                },
+                Include {
+                    macro_name: "include".into(),
+                    path: None,
+                },
+            ]
+        );
+    }
+}
@@ -1,58 +1,131 @@
 use std::collections::HashSet;
 use std::fs;
-use std::iter;
 use std::path::PathBuf;
 
 use proc_macro2::{Ident, TokenStream, TokenTree};
 use quote::ToTokens;
 
 use crate::errors::CargoPlayError;
+use crate::opt::InferLevel;
 
-const USE_KEYWORDS: &'static [&'static str] = &["std", "core", "crate", "self", "alloc", "super"];
+const USE_KEYWORDS: &[&str] = &["std", "core", "crate", "self", "alloc", "super"];
 
-fn extra_use<'a, T: 'a + IntoIterator<Item = TokenTree> + Clone>(
-    input: T,
-) -> Box<dyn Iterator<Item = Ident> + 'a> {
-    use proc_macro2::Literal;
+/// Names imported by `use` statements, along with whether they are followed by a path
+/// (`use name::item`) rather than imported on their own (`use name;`).
+fn extra_use(input: TokenStream) -> Vec<(Ident, bool)> {
     use TokenTree as tt;
 
-    Box::new(
-        input
-            .clone()
-            .into_iter()
-            .zip(
-                input
-                    .into_iter()
-                    .skip(1)
-                    .chain(iter::once(Literal::u8_suffixed(1u8).into())),
-            )
-            .flat_map(|(prev, current)| match (prev, current) {
-                (tt::Ident(ref first), tt::Ident(ref second)) if first.to_string() == "use" => {
-                    Box::new(iter::once(second.clone()))
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut found = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            tt::Ident(keyword) if keyword == "use" => {
+                if let Some(tt::Ident(name)) = tokens.get(index + 1) {
+                    let is_path = matches!(
+                        tokens.get(index + 2),
+                        Some(tt::Punct(punct)) if punct.as_char() == ':'
+                    );
+                    found.push((name.clone(), is_path));
                 }
-                (tt::Group(ref group), _) => extra_use(group.stream()),
-                _ => Box::new(iter::empty()),
-            }),
-    )
+            }
+            tt::Group(group) => found.extend(extra_use(group.stream())),
+            _ => {}
+        }
+    }
+
+    found
+}
+
+/// Names of the modules of the crate itself, which `use` statements may refer to as well: the
+/// top-level `mod` items and the additional source files.
+fn local_modules(sources: &[PathBuf], files: &[syn::File]) -> HashSet<String> {
+    let declared = files.iter().flat_map(|file| {
+        file.items.iter().filter_map(|item| match item {
+            syn::Item::Mod(module) => Some(module.ident.to_string()),
+            _ => None,
+        })
+    });
+    let files = sources
+        .iter()
+        .skip(1)
+        .filter_map(|source| source.file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned());
+
+    declared.chain(files).collect()
 }
 
-pub fn analyze_sources(sources: &Vec<PathBuf>) -> Result<HashSet<String>, CargoPlayError> {
+/// Infer the crates used by the sources from their `use` statements.
+///
+/// * `Aggressive` takes the first segment of every `use` as a crate name.
+/// * `Conservative` skips names imported on their own (`use log;`), which may as well be macros or
+///   local items, and names of the crate's own modules.
+pub fn analyze_sources(
+    sources: &[PathBuf],
+    level: InferLevel,
+) -> Result<HashSet<String>, CargoPlayError> {
     let contents: Vec<_> = sources
-        .into_iter()
+        .iter()
         .map(fs::read_to_string)
         .collect::<Result<_, _>>()?;
 
-    let streams: Vec<TokenStream> = contents
-        .into_iter()
-        .map(|file| -> Result<_, CargoPlayError> {
-            Ok(syn::parse_file(&file)?.into_token_stream())
-        })
-        .collect::<Result<_, CargoPlayError>>()?;
+    let files: Vec<syn::File> = contents
+        .iter()
+        .map(|file| syn::parse_file(file))
+        .collect::<Result<_, _>>()?;
+    let local = local_modules(sources, &files);
 
-    Ok(streams
-        .into_iter()
-        .flat_map(|token| extra_use(token.into_iter()))
-        .map(|ident| ident.to_string())
+    Ok(files
+        .iter()
+        .flat_map(|file| extra_use(file.into_token_stream()))
+        .filter(|(_, is_path)| level == InferLevel::Aggressive || *is_path)
+        .map(|(ident, _)| ident.to_string())
         .filter(|ident| !USE_KEYWORDS.contains(&ident.as_ref()))
+        .filter(|ident| level == InferLevel::Aggressive || !local.contains(ident))
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(source: &str, level: InferLevel) -> HashSet<String> {
+        let path =
+            std::env::temp_dir().join(format!("cargo-play-infer-{}.rs", rand::random::<u64>()));
+        fs::write(&path, source).unwrap();
+        let result = analyze_sources(std::slice::from_ref(&path), level);
+        fs::remove_file(path).unwrap();
+        result.unwrap()
+    }
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_infer_levels() {
+        let source = r#"
+            mod util {
+                pub fn helper() {}
+            }
+
+            use log;
+            use serde::Serialize;
+            use util::helper;
+            use std::io;
+
+            fn main() {
+                use rand::Rng;
+            }
+        "#;
+
+        assert_eq!(
+            infer(source, InferLevel::Aggressive),
+            names(&["log", "serde", "util", "rand"])
+        );
+        assert_eq!(
+            infer(source, InferLevel::Conservative),
+            names(&["serde", "rand"])
+        );
+    }
+}
@@ -1,4 +1,17 @@
 mod cargo;
 mod errors;
+mod explain;
+mod includes;
+mod infer;
+pub mod logger;
+mod macros;
 pub mod opt;
+mod playground;
+mod pty;
 pub mod steps;
+mod templates;
+
+pub use crate::cargo::CargoManifest;
+pub use crate::errors::CargoPlayError;
+pub use crate::opt::Opt;
+pub use crate::playground::Playground;
@@ -0,0 +1,155 @@
+//! Logger printing cargo-play's own messages to stderr, and to the `--log-file` if any.
+
+use std::fs::File;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{info, Level, LevelFilter, Log, Metadata, Record};
+
+use crate::errors::CargoPlayError;
+
+/// Target of the cargo-style status lines, see `status`.
+const STATUS: &str = "cargo_play::status";
+
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+    /// Whether the verb of status lines is highlighted on stderr
+    color: bool,
+}
+
+impl Logger {
+    fn format(record: &Record) -> String {
+        match record.level() {
+            Level::Error => format!("error: {}", record.args()),
+            Level::Warn => format!("warning: {}", record.args()),
+            Level::Info => format!("{}", record.args()),
+            Level::Debug | Level::Trace => {
+                format!(
+                    "[{}] {}: {}",
+                    record.level(),
+                    record.target(),
+                    record.args()
+                )
+            }
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = Self::format(record);
+        if self.color && record.target() == STATUS {
+            eprintln!("{}", highlight(&line));
+        } else {
+            eprintln!("{}", line);
+        }
+
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// The status `line` with its verb in bold green, like cargo's own status lines.
+fn highlight(line: &str) -> String {
+    let verb = line.len() - line.trim_start().len();
+    let end = line[verb..].find(' ').map_or(line.len(), |end| verb + end);
+
+    format!("\x1b[1;32m{}\x1b[0m{}", &line[..end], &line[end..])
+}
+
+/// Log a status line aligned like cargo's, e.g. `   Searching serde on the registry`.
+pub fn status(verb: &str, message: std::fmt::Arguments) {
+    info!(target: STATUS, "{:>12} {}", verb, message);
+}
+
+/// The level set for cargo-play by a `RUST_LOG` value, either as a bare level like `debug` or
+/// for the `cargo_play` target like `cargo_play=trace`, which wins over the former. Directives
+/// for other targets are ignored, cargo-play has no dependencies worth logging.
+fn env_level(value: &str) -> Option<LevelFilter> {
+    let mut level = None;
+
+    for directive in value.split(',').map(str::trim) {
+        match directive.split_once('=') {
+            Some((target, filter)) if target.split("::").next() == Some("cargo_play") => {
+                return filter.parse().ok();
+            }
+            Some(_) => {}
+            None => level = directive.parse().ok().or(level),
+        }
+    }
+
+    level
+}
+
+/// Install the logger. Debug messages are shown with `--debug`, which overrides `RUST_LOG`;
+/// otherwise `RUST_LOG` picks the level, or cargo-play's regular messages are shown.
+pub fn init(debug: bool, log_file: Option<&Path>) -> Result<(), CargoPlayError> {
+    let level = if debug {
+        LevelFilter::Debug
+    } else {
+        std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|value| env_level(&value))
+            .unwrap_or(LevelFilter::Info)
+    };
+    let file = log_file.map(File::create).transpose()?.map(Mutex::new);
+    let color = std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && std::io::stderr().is_terminal();
+    let logger = Box::leak(Box::new(Logger { level, file, color }));
+
+    // a logger can only be installed once, which only matters to tests
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_level() {
+        assert_eq!(env_level("debug"), Some(LevelFilter::Debug));
+        assert_eq!(env_level("WARN"), Some(LevelFilter::Warn));
+        assert_eq!(env_level("off"), Some(LevelFilter::Off));
+        assert_eq!(
+            env_level("cargo_play::steps=trace,error"),
+            Some(LevelFilter::Trace)
+        );
+        assert_eq!(env_level("error, cargo=debug"), Some(LevelFilter::Error));
+        assert_eq!(env_level("cargo=debug"), None);
+        assert_eq!(env_level("verbose"), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("   Searching serde on the registry"),
+            "\x1b[1;32m   Searching\x1b[0m serde on the registry"
+        );
+        assert_eq!(highlight("Finished"), "\x1b[1;32mFinished\x1b[0m");
+    }
+}
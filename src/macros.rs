@@ -0,0 +1,203 @@
+//! Check that the derive and attribute macros of a snippet are provided by its dependencies, see
+//! `--pre-expand-macros`. A crate declared without the feature enabling its macros otherwise
+//! fails with a confusing "cannot find derive macro" error.
+
+use toml::value::{Table, Value};
+
+/// A macro provided by a crate only when one of the features is enabled.
+struct Provider {
+    /// Name of a derive macro, or path of an attribute macro.
+    name: &'static str,
+    derive: bool,
+    krate: &'static str,
+    features: &'static [&'static str],
+    /// A crate providing the macro without any feature, e.g. `serde_derive`.
+    alternative: Option<&'static str>,
+}
+
+const fn derive(
+    name: &'static str,
+    krate: &'static str,
+    alternative: Option<&'static str>,
+) -> Provider {
+    Provider {
+        name,
+        derive: true,
+        krate,
+        features: &["derive"],
+        alternative,
+    }
+}
+
+const fn attribute(
+    name: &'static str,
+    krate: &'static str,
+    features: &'static [&'static str],
+) -> Provider {
+    Provider {
+        name,
+        derive: false,
+        krate,
+        features,
+        alternative: None,
+    }
+}
+
+const PROVIDERS: &[Provider] = &[
+    derive("Serialize", "serde", Some("serde_derive")),
+    derive("Deserialize", "serde", Some("serde_derive")),
+    derive("Parser", "clap", None),
+    derive("Args", "clap", None),
+    derive("Subcommand", "clap", None),
+    derive("ValueEnum", "clap", None),
+    attribute("tokio::main", "tokio", &["macros", "full"]),
+    attribute("tokio::test", "tokio", &["macros", "full"]),
+];
+
+/// The derive macros and the attribute paths used by the items, including in inline modules.
+fn macro_uses(items: &[syn::Item], derives: &mut Vec<String>, attributes: &mut Vec<String>) {
+    for item in items {
+        let attrs = match item {
+            syn::Item::Struct(item) => &item.attrs,
+            syn::Item::Enum(item) => &item.attrs,
+            syn::Item::Union(item) => &item.attrs,
+            syn::Item::Fn(item) => &item.attrs,
+            syn::Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    macro_uses(items, derives, attributes);
+                }
+                &item.attrs
+            }
+            _ => continue,
+        };
+
+        for attr in attrs {
+            if attr.path.is_ident("derive") {
+                if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+                    derives.extend(list.nested.iter().filter_map(|nested| match nested {
+                        syn::NestedMeta::Meta(meta) => {
+                            meta.path().segments.last().map(|s| s.ident.to_string())
+                        }
+                        _ => None,
+                    }));
+                }
+            } else {
+                let segments: Vec<String> = attr
+                    .path
+                    .segments
+                    .iter()
+                    .map(|segment| segment.ident.to_string())
+                    .collect();
+                attributes.push(segments.join("::"));
+            }
+        }
+    }
+}
+
+/// The features enabled for a dependency spec.
+fn features(spec: &Value) -> Vec<&str> {
+    spec.get("features")
+        .and_then(Value::as_array)
+        .map(|features| features.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// Hints for the macros used by `source` whose crate is among the `dependencies` without the
+/// feature providing them. Sources that can't be parsed have none, rustc reports the problem
+/// instead.
+pub fn missing_providers(source: &str, dependencies: &Table) -> Vec<String> {
+    let file = match syn::parse_file(source) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+    let (mut derives, mut attributes) = (Vec::new(), Vec::new());
+    macro_uses(&file.items, &mut derives, &mut attributes);
+
+    let dependency = |name: &str| {
+        dependencies
+            .iter()
+            .find(|(key, _)| key.replace('-', "_") == name)
+            .map(|(_, spec)| spec)
+    };
+    let mut hints = Vec::new();
+
+    for provider in PROVIDERS {
+        let used = if provider.derive {
+            derives.iter().any(|name| name == provider.name)
+        } else {
+            attributes.iter().any(|path| path == provider.name)
+        };
+        let spec = match dependency(provider.krate) {
+            Some(spec) if used => spec,
+            _ => continue,
+        };
+
+        let enabled = features(spec);
+        if provider.features.iter().any(|f| enabled.contains(f))
+            || provider
+                .alternative
+                .is_some_and(|alt| dependency(alt).is_some())
+        {
+            continue;
+        }
+
+        let usage = if provider.derive {
+            format!("#[derive({})]", provider.name)
+        } else {
+            format!("#[{}]", provider.name)
+        };
+        hints.push(format!(
+            "`{}` needs the `{}` feature of `{}`, e.g. `//# {} = {{ version = \"*\", features = [\"{}\"] }}`",
+            usage, provider.features[0], provider.krate, provider.krate, provider.features[0]
+        ));
+    }
+
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies(manifest: &str) -> Table {
+        toml::from_str(manifest).unwrap()
+    }
+
+    #[test]
+    fn test_missing_providers() {
+        let source = r#"
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Point {
+    x: i32,
+}
+
+#[tokio::main]
+async fn main() {}
+"#;
+
+        let hints = missing_providers(source, &dependencies("serde = \"1\"\ntokio = \"1\""));
+        assert_eq!(hints.len(), 2);
+        assert!(
+            hints[0].starts_with("`#[derive(Serialize)]` needs the `derive` feature of `serde`")
+        );
+        assert!(hints[1].starts_with("`#[tokio::main]` needs the `macros` feature of `tokio`"));
+
+        let hints = missing_providers(
+            source,
+            &dependencies(
+                "serde = { version = \"1\", features = [\"derive\"] }\n\
+                 tokio = { version = \"1\", features = [\"full\"] }",
+            ),
+        );
+        assert!(hints.is_empty());
+
+        assert!(
+            missing_providers(source, &dependencies("serde = \"1\"\nserde_derive = \"1\""))
+                .is_empty()
+        );
+        // undeclared crates are left to rustc
+        assert!(missing_providers(source, &Table::new()).is_empty());
+    }
+}
@@ -1,81 +1,290 @@
-mod cargo;
-mod errors;
-mod infer;
-mod opt;
-mod steps;
-
-use std::collections::HashSet;
-use std::iter::Iterator;
-use std::process::{Command, Stdio};
+use std::io::IsTerminal;
+use std::process::Stdio;
 use std::vec::Vec;
 
-use crate::errors::CargoPlayError;
-use crate::opt::Opt;
-use crate::steps::*;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+
+use cargo_play::logger;
+use cargo_play::opt::{self, CargoAction, Invocation, OutputFormat};
+use cargo_play::steps::*;
+use cargo_play::{CargoPlayError, Opt, Playground};
 
 fn main() -> Result<(), CargoPlayError> {
     let args = std::env::args().collect::<Vec<_>>();
-    let opt = Opt::parse(args);
-    if opt.is_err() {
-        return Ok(());
+    let mut opt = match Opt::parse(args) {
+        Ok(Invocation::Play(opt)) => *opt,
+        Ok(Invocation::ListCache) => return list_cache(),
+        Ok(Invocation::New(new)) => return new_snippet(&new),
+        Ok(Invocation::VersionOf(name)) => return version_of(&name),
+        Ok(Invocation::ListEditions) => return list_editions(),
+        Ok(Invocation::ListToolchains) => return list_toolchains(),
+        Err(_) => return Ok(()),
+    };
+
+    logger::init(opt.debug, opt.log_file.as_deref())?;
+
+    if opt.reads_stdin() {
+        if opt.src.len() > 1 {
+            return Err(CargoPlayError::ParseError(
+                "a source read from stdin can't be combined with other sources".into(),
+            ));
+        }
+        opt.src[0] = save_stdin_source(&opt)?;
+        debug!("Saved the source read from stdin to {:?}", opt.src[0]);
+    }
+
+    // each source of --separate and --workspace is looked at on its own
+    if !opt.separate && !opt.workspace && runs_tests_only(&opt)? {
+        debug!("{:?} only has tests, building it as a library", opt.src[0]);
+        opt.lib = true;
+    }
+
+    if !opt.no_interactive
+        && opt.cargo_action() == CargoAction::Run
+        && std::io::stdout().is_terminal()
+    {
+        // interactive users want the program's output, not the progress of successful builds
+        debug!("stdout is a terminal, enabling --quiet-on-success");
+        opt.quiet_on_success = true;
+    }
+
+    for warning in opt.cargo_option_warnings() {
+        warn!("{}", warning);
+    }
+
+    if opt.fast && std::env::var_os("CARGO_TARGET_DIR").is_none() {
+        // dependencies built for one snippet are reused by the others
+        std::env::set_var("CARGO_TARGET_DIR", shared_target_dir());
+    }
+
+    if opt.scratch {
+        opt.no_cache = true;
+    }
+
+    if opt.no_cache && std::env::var_os("CARGO_TARGET_DIR").is_some() {
+        // a shared target directory would still serve the artifacts of previous builds
+        debug!("Ignoring CARGO_TARGET_DIR for --no-cache");
+        std::env::remove_var("CARGO_TARGET_DIR");
+    }
+
+    if opt.separate {
+        std::process::exit(play_separately(opt)?);
+    }
+    if opt.workspace {
+        std::process::exit(play_workspace(opt)?);
+    }
+
+    let (output_format, wrap_errors) = (opt.output_format, opt.wrap_errors);
+    // dropped before exiting, which removes the project of --no-cache
+    let result = play(Playground::new(opt));
+
+    if output_format == OutputFormat::Json {
+        let report = Report::new(&result, wrap_errors);
+        println!(
+            "{}",
+            serde_json::to_string(&report).map_err(CargoPlayError::from_serde)?
+        );
+        match result {
+            Err(CargoPlayError::BuildFailed(_)) | Ok(_) => {}
+            Err(e) => error!("{}", e),
+        }
+        std::process::exit(report.exit_code);
+    }
+
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(e) if wrap_errors => {
+            error!("{}", e);
+            std::process::exit(opt::EXIT_USAGE)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Outcome of a run, printed by `--output-format json`.
+#[derive(Debug, Serialize)]
+struct Report {
+    success: bool,
+    /// `setup`, `build` or `run`: the stage cargo-play ended in
+    stage: &'static str,
+    exit_code: i32,
+    /// Kind of the error that stopped cargo-play before the run, if any
+    error: Option<String>,
+    message: Option<String>,
+}
+
+impl Report {
+    fn new(result: &Result<i32, CargoPlayError>, wrap_errors: bool) -> Self {
+        let (stage, exit_code) = match result {
+            Ok(code) => ("run", *code),
+            Err(CargoPlayError::BuildFailed(_)) if wrap_errors => {
+                ("build", opt::EXIT_BUILD_FAILURE)
+            }
+            Err(CargoPlayError::BuildFailed(code)) => ("build", code.unwrap_or(-1)),
+            Err(_) if wrap_errors => ("setup", opt::EXIT_USAGE),
+            Err(_) => ("setup", 1),
+        };
+
+        Report {
+            success: result.is_ok() && exit_code == 0,
+            stage,
+            exit_code,
+            error: result.as_ref().err().map(CargoPlayError::kind),
+            message: result.as_ref().err().map(ToString::to_string),
+        }
     }
-    let opt = opt.unwrap();
+}
 
-    let src_hash = opt.src_hash();
-    let temp = temp_dir(opt.temp_dirname());
+/// Play every source as a snippet of its own, see `--separate`, and summarize the results.
+/// Returns the exit code of the first failing snippet.
+fn play_separately(opt: Opt) -> Result<i32, CargoPlayError> {
+    let mut results: Vec<_> = opt
+        .src
+        .iter()
+        .map(|src| (src.display().to_string(), BatchOutcome::Skipped))
+        .collect();
 
-    if opt.cached && temp.exists() {
-        let mut bin_path = temp.join("target");
-        if opt.release {
-            bin_path.push("release");
-        } else {
-            bin_path.push("debug");
+    for (src, (_, outcome)) in opt.src.iter().zip(results.iter_mut()) {
+        let mut single = Opt {
+            src: vec![src.clone()],
+            ..opt.clone()
+        };
+        if runs_tests_only(&single)? {
+            single.lib = true;
         }
-        // TODO reuse logic to formulate package name, i.e. to_lowercase
-        bin_path.push(&src_hash.to_lowercase());
-        if bin_path.exists() {
-            let mut cmd = Command::new(bin_path);
-            return cmd
-                .args(opt.args)
-                .stderr(Stdio::inherit())
-                .stdout(Stdio::inherit())
-                .status()
-                .map(|_| ())
-                .map_err(CargoPlayError::from);
+
+        let exit_code = match play(Playground::new(single)) {
+            Ok(exit_code) => exit_code,
+            Err(e) => {
+                error!("{}: {}", src.display(), e);
+                if opt.wrap_errors {
+                    opt::EXIT_USAGE
+                } else {
+                    1
+                }
+            }
+        };
+        *outcome = exit_code.into();
+
+        if exit_code != 0 && !opt.keep_going {
+            break;
         }
     }
 
-    let files = parse_inputs(&opt.src)?;
-    let dependencies = extract_headers(&files);
+    for line in batch_summary(&results) {
+        info!("{}", line);
+    }
 
-    let infers = if opt.infer {
-        infer::analyze_sources(&opt.src)?
-    } else {
-        HashSet::new()
-    };
+    Ok(results
+        .iter()
+        .find_map(|(_, outcome)| match outcome {
+            BatchOutcome::Failed(code) => *code,
+            _ => None,
+        })
+        .unwrap_or(0))
+}
+
+/// Generate a workspace with a member for every source and build it, see `--workspace`, then
+/// summarize how each member fared. Returns the exit code of cargo.
+fn play_workspace(opt: Opt) -> Result<i32, CargoPlayError> {
+    let dir = temp_dir(format!("cargo-play-workspace.{}", opt.src_hash()).into());
+    let members: Vec<_> = opt.src.iter().map(|src| member_name(src)).collect();
+
+    if let Some(src) = opt.src.iter().find(|src| src.is_dir()) {
+        return Err(CargoPlayError::ParseError(format!(
+            "{} is a project, --workspace only takes source files",
+            src.display()
+        )));
+    }
+    for (index, member) in members.iter().enumerate() {
+        if members[..index].contains(member) {
+            return Err(CargoPlayError::ParseError(format!(
+                "two sources make the --workspace member `{}`",
+                member
+            )));
+        }
+    }
 
     if opt.clean {
-        rmtemp(&temp);
-    }
-    mktemp(&temp);
-    write_cargo_toml(&temp, src_hash.clone(), dependencies, opt.edition, infers)?;
-    copy_sources(&temp, &opt.src)?;
-
-    let end = if let Some(save) = opt.save {
-        copy_project(&temp, &save)?
-    } else {
-        run_cargo_build(
-            opt.toolchain,
-            &temp,
-            opt.release,
-            opt.cargo_option,
-            &opt.args,
-        )?
-    };
+        rmtemp(&dir);
+    }
+    mktemp(&dir);
+    write_workspace_manifest(&dir, &members)?;
+
+    for (src, member) in opt.src.iter().zip(&members) {
+        let single = Opt {
+            src: vec![src.clone()],
+            name: Some(member.clone()),
+            lib: is_library(&std::fs::read_to_string(src)?),
+            workspace: false,
+            clean: false,
+            ..opt.clone()
+        };
+        Playground::in_dir(single, dir.join(member)).prepare()?;
+    }
 
-    match end.code() {
-        Some(code) => std::process::exit(code),
-        None => std::process::exit(-1),
+    let mut cargo = build_command(&opt, &dir);
+    cargo
+        .args(["--workspace", "--message-format", "json"])
+        .envs(env_vars(&opt)?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+    if opt.keep_going {
+        cargo.arg("--keep-going");
+    }
+
+    debug!("Building {:?}", cargo);
+    let mut build = cargo.spawn()?;
+    let outcomes = build
+        .stdout
+        .take()
+        .map(|stdout| member_outcomes(&dir, &members, std::io::BufReader::new(stdout)))
+        .transpose()?
+        .unwrap_or_default();
+    let status = build.wait()?;
+
+    let results: Vec<_> = members.into_iter().zip(outcomes).collect();
+    for line in batch_summary(&results) {
+        info!("{}", line);
+    }
+
+    Ok(status.code().unwrap_or(-1))
+}
+
+/// Set up and run the project, returning the exit code of the program.
+fn play(playground: Playground) -> Result<i32, CargoPlayError> {
+    let opt = playground.opt();
+
+    if opt.print_target_dir {
+        println!("{}", target_dir(opt, playground.project()).display());
+        return Ok(0);
+    }
+
+    if opt.clipboard || opt.emit_manifest.is_some() {
+        let manifest = playground.manifest()?;
+        if opt.clipboard {
+            copy_to_clipboard(&manifest)?;
+        }
+        if let Some(path) = &opt.emit_manifest {
+            emit_manifest(path, &manifest)?;
+        }
+        return Ok(0);
+    }
+
+    if let Some(save) = &opt.save {
+        copy_project(playground.prepare()?, save)?;
+        return Ok(0);
+    }
+
+    match playground.run() {
+        Ok(status) => Ok(status.code().unwrap_or(-1)),
+        Err(CargoPlayError::BuildFailed(_))
+            if opt.wrap_errors && opt.output_format != OutputFormat::Json =>
+        {
+            Ok(opt::EXIT_BUILD_FAILURE)
+        }
+        Err(e) => Err(e),
     }
 }
 
@@ -83,21 +292,106 @@ fn main() -> Result<(), CargoPlayError> {
 mod tests {
     use super::*;
 
+    fn headers(lines: &[&str]) -> Vec<Header> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(index, text)| Header {
+                text: text.to_string(),
+                path: "snippet.rs".into(),
+                line: index + 1,
+                frontmatter: false,
+            })
+            .collect()
+    }
+
     #[test]
     fn test_extract_headers() {
         let inputs: Vec<String> = vec![
-            r#"//# line 1
+            r#"#!/usr/bin/env run-cargo-script
 //# line 2
-// line 3
-//# line 4"#,
+//# line 3
+// line 4
+//# line 5"#,
         ]
         .into_iter()
         .map(Into::into)
         .collect();
-        let result = extract_headers(&inputs);
+        let result = extract_headers(&["snippet.rs".into()], &inputs);
 
         assert_eq!(result.len(), 2);
-        assert_eq!(result[0], String::from("line 1"));
-        assert_eq!(result[1], String::from("line 2"));
+        assert_eq!(result[0].text, String::from("line 2"));
+        assert_eq!(result[1].text, String::from("line 3"));
+        assert_eq!(result[1].path, std::path::PathBuf::from("snippet.rs"));
+        assert_eq!(result[1].line, 3);
+    }
+
+    #[test]
+    fn test_extract_frontmatter() {
+        let inputs = vec![String::from(
+            r#"#!/usr/bin/env run-cargo-script
+//# edition = "2018"
+//# ---
+//# [dependencies]
+//# serde = { version = "1", features = ["derive"] }
+//#
+//# [profile.dev]
+//# opt-level = 1
+//# ---
+//# log = "0.4"
+
+fn main() {}
+"#,
+        )];
+        let headers = extract_headers(&["snippet.rs".into()], &inputs);
+        let (dependencies, package) = split_package_headers(headers).unwrap();
+
+        assert_eq!(dependencies, vec![String::from(r#"log = "0.4""#)]);
+        assert_eq!(package.edition, Some(opt::RustEdition::E2018));
+        assert_eq!(
+            package.frontmatter,
+            "[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\n\
+             [profile.dev]\nopt-level = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_split_package_headers() {
+        let (dependencies, package) = split_package_headers(headers(&[
+            r#"edition = "2015""#,
+            r#"serde = "1""#,
+            r#"bin: name = "tool""#,
+        ]))
+        .unwrap();
+
+        assert_eq!(dependencies, vec![String::from(r#"serde = "1""#)]);
+        assert_eq!(package.edition, Some(opt::RustEdition::E2015));
+        assert_eq!(package.bins.len(), 1);
+        assert_eq!(package.bins[0]["name"].as_str(), Some("tool"));
+        assert_eq!(package.bins[0]["path"].as_str(), Some("src/main.rs"));
+
+        let (dependencies, package) = split_package_headers(headers(&["edition: 2018"])).unwrap();
+
+        assert!(dependencies.is_empty());
+        assert_eq!(package.edition, Some(opt::RustEdition::E2018));
+
+        let (_, package) = split_package_headers(headers(&["rust-version: 1.70"])).unwrap();
+
+        assert_eq!(package.rust_version.as_deref(), Some("1.70"));
+
+        assert!(split_package_headers(headers(&[r#"edition = "1999""#])).is_err());
+        assert!(split_package_headers(headers(&["rust-version: new"])).is_err());
+        assert!(split_package_headers(headers(&[r#"bin: path = "src/main.rs""#])).is_err());
+    }
+
+    #[test]
+    fn test_invalid_dependency_header() {
+        let error = split_package_headers(headers(&[r#"serde = "1""#, "dev: rand"])).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Invalid dependency header `dev: rand` at snippet.rs:2, expected `name = spec` such \
+             as `serde = \"1\"`"
+        );
     }
 }
@@ -3,91 +3,1056 @@ use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::vec::Vec;
+use structopt::clap::{self, ErrorKind};
 use structopt::StructOpt;
 
 use crate::errors::CargoPlayError;
+use crate::templates;
 
-#[derive(Debug)]
+/// The source argument reading the source from stdin.
+pub const STDIN: &str = "-";
+
+/// Exit code of `--wrap-errors` for invalid arguments or sources cargo-play can't handle.
+pub const EXIT_USAGE: i32 = 64;
+/// Exit code of `--wrap-errors` for a failed build.
+pub const EXIT_BUILD_FAILURE: i32 = 65;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum RustEdition {
     E2015,
     E2018,
+    #[default]
+    E2021,
+    E2024,
+}
+
+impl RustEdition {
+    /// Every edition supported by `--edition`, oldest first.
+    pub const ALL: &'static [RustEdition] = &[
+        RustEdition::E2015,
+        RustEdition::E2018,
+        RustEdition::E2021,
+        RustEdition::E2024,
+    ];
+
+    /// The first rustc version, as major and minor, supporting this edition.
+    pub fn min_rustc(self) -> (u32, u32) {
+        match self {
+            RustEdition::E2015 => (1, 0),
+            RustEdition::E2018 => (1, 31),
+            RustEdition::E2021 => (1, 56),
+            RustEdition::E2024 => (1, 85),
+        }
+    }
+
+    /// The resolver cargo picks for packages of this edition, when it isn't the default "1".
+    pub fn default_resolver(self) -> Option<&'static str> {
+        match self {
+            RustEdition::E2015 | RustEdition::E2018 => None,
+            RustEdition::E2021 => Some("2"),
+            RustEdition::E2024 => Some("3"),
+        }
+    }
 }
 
 impl FromStr for RustEdition {
     type Err = CargoPlayError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "2018" {
-            Ok(RustEdition::E2018)
-        } else if s == "2015" {
-            Ok(RustEdition::E2015)
-        } else {
-            Err(CargoPlayError::InvalidEdition(s.into()))
+        match s {
+            "2015" => Ok(RustEdition::E2015),
+            "2018" => Ok(RustEdition::E2018),
+            "2021" => Ok(RustEdition::E2021),
+            "2024" => Ok(RustEdition::E2024),
+            _ => Err(CargoPlayError::InvalidEdition(s.into())),
         }
     }
 }
 
-impl Into<String> for RustEdition {
-    fn into(self) -> String {
-        match self {
+impl From<RustEdition> for String {
+    fn from(edition: RustEdition) -> Self {
+        match edition {
             RustEdition::E2015 => "2015".into(),
             RustEdition::E2018 => "2018".into(),
+            RustEdition::E2021 => "2021".into(),
+            RustEdition::E2024 => "2024".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CargoAction {
+    Run,
+    Test,
+    Bench,
+    Build,
+    Check,
+    Clippy,
+    Expand,
+}
+
+impl CargoAction {
+    /// The cargo subcommand performing this action.
+    pub fn subcommand(self) -> &'static str {
+        match self {
+            CargoAction::Run => "run",
+            CargoAction::Test => "test",
+            CargoAction::Bench => "bench",
+            CargoAction::Build => "build",
+            CargoAction::Check => "check",
+            CargoAction::Clippy => "clippy",
+            CargoAction::Expand => "expand",
+        }
+    }
+
+    /// The cargo subcommand compiling everything this action needs, without running it.
+    pub fn build_subcommand(self) -> &'static [&'static str] {
+        match self {
+            CargoAction::Run | CargoAction::Build => &["build"],
+            CargoAction::Test => &["test", "--no-run"],
+            CargoAction::Bench => &["bench", "--no-run"],
+            CargoAction::Check | CargoAction::Expand => &["check"],
+            CargoAction::Clippy => &["clippy"],
+        }
+    }
+
+    /// Whether arguments after `--` are passed on to the program, the test harness, or clippy
+    /// as lint flags.
+    pub fn forwards_args(self) -> bool {
+        match self {
+            CargoAction::Run | CargoAction::Test | CargoAction::Bench | CargoAction::Clippy => true,
+            CargoAction::Build | CargoAction::Check | CargoAction::Expand => false,
+        }
+    }
+
+    /// The binary providing the subcommand, when it doesn't ship with cargo.
+    pub fn external_subcommand(self) -> Option<&'static str> {
+        match self {
+            CargoAction::Expand => Some("cargo-expand"),
+            _ => None,
+        }
+    }
+}
+
+/// How eagerly `--infer` turns `use` statements into dependencies, see `infer::analyze_sources`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InferLevel {
+    Conservative,
+    #[default]
+    Aggressive,
+}
+
+impl FromStr for InferLevel {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "conservative" => Ok(InferLevel::Conservative),
+            "aggressive" => Ok(InferLevel::Aggressive),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown infer level {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How the version requirement of inferred crates is written.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VersionStyle {
+    /// `*`
+    #[default]
+    Wildcard,
+    /// `^x.y` of the latest version
+    Caret,
+    /// `=x.y.z` of the latest version
+    Exact,
+}
+
+impl FromStr for VersionStyle {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wildcard" => Ok(VersionStyle::Wildcard),
+            "caret" => Ok(VersionStyle::Caret),
+            "exact" => Ok(VersionStyle::Exact),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown version style {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl VersionStyle {
+    /// The requirement for the given latest version, falling back to `*` when it's unknown.
+    pub fn requirement(self, latest: Option<&str>) -> String {
+        match (self, latest) {
+            (VersionStyle::Caret, Some(latest)) => {
+                let major_minor: Vec<&str> = latest.split('.').take(2).collect();
+                format!("^{}", major_minor.join("."))
+            }
+            (VersionStyle::Exact, Some(latest)) => format!("={}", latest),
+            _ => "*".into(),
+        }
+    }
+}
+
+/// How the generated manifest is written, see `--manifest-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ManifestFormat {
+    /// Plain `toml` serialization, with a section per table dependency
+    #[default]
+    Toml,
+    /// Hand-written style, with inline tables for dependencies and a comment header
+    Pretty,
+}
+
+impl FromStr for ManifestFormat {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toml" => Ok(ManifestFormat::Toml),
+            "pretty" => Ok(ManifestFormat::Pretty),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown manifest format {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How cargo-play reports the outcome of a run, see `--output-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// Only the output of cargo and the program
+    #[default]
+    Human,
+    /// A JSON object on stdout once cargo-play is done, on success and failure alike
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown output format {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Digest naming the temporary projects, see `--hash`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown hash algorithm {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    fn digest(self, input: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha1 => sha1::Sha1::from(input).digest().bytes().to_vec(),
+            HashAlgorithm::Sha256 => {
+                use sha2::Digest;
+                sha2::Sha256::digest(input).to_vec()
+            }
+        }
+    }
+}
+
+/// Panic strategy of the dev and release profiles, see `--panic`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanicStrategy {
+    Unwind,
+    Abort,
+}
+
+impl FromStr for PanicStrategy {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unwind" => Ok(PanicStrategy::Unwind),
+            "abort" => Ok(PanicStrategy::Abort),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown panic strategy {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl PanicStrategy {
+    /// The value of the `panic` profile key.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PanicStrategy::Unwind => "unwind",
+            PanicStrategy::Abort => "abort",
+        }
+    }
+}
+
+/// Global allocator wired into the generated project, see `--allocator`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Allocator {
+    Jemalloc,
+    Mimalloc,
+    System,
+}
+
+impl FromStr for Allocator {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jemalloc" => Ok(Allocator::Jemalloc),
+            "mimalloc" => Ok(Allocator::Mimalloc),
+            "system" => Ok(Allocator::System),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown allocator {:?}",
+                s
+            ))),
         }
     }
 }
 
-impl Default for RustEdition {
-    fn default() -> Self {
-        RustEdition::E2018
+impl Allocator {
+    /// The `//#` header of the crate providing the allocator, if it isn't part of std.
+    pub fn dependency(self) -> Option<&'static str> {
+        match self {
+            Allocator::Jemalloc => Some(r#"tikv-jemallocator = "0.5""#),
+            Allocator::Mimalloc => Some(r#"mimalloc = "0.1""#),
+            Allocator::System => None,
+        }
+    }
+
+    /// Path of the allocator type, which is also a unit struct value.
+    pub fn type_path(self) -> &'static str {
+        match self {
+            Allocator::Jemalloc => "tikv_jemallocator::Jemalloc",
+            Allocator::Mimalloc => "mimalloc::MiMalloc",
+            Allocator::System => "std::alloc::System",
+        }
+    }
+}
+
+/// Intermediate compiler output to inspect, see `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitKind {
+    Asm,
+    LlvmIr,
+    Mir,
+    Metadata,
+}
+
+impl FromStr for EmitKind {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asm" => Ok(EmitKind::Asm),
+            "llvm-ir" => Ok(EmitKind::LlvmIr),
+            "mir" => Ok(EmitKind::Mir),
+            "metadata" => Ok(EmitKind::Metadata),
+            _ => Err(CargoPlayError::ParseError(format!(
+                "unknown emit kind {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl EmitKind {
+    /// The rustc flag emitting this kind of output, in addition to the regular artifacts.
+    pub fn rustc_flag(self) -> &'static str {
+        match self {
+            EmitKind::Asm => "--emit=asm",
+            EmitKind::LlvmIr => "--emit=llvm-ir",
+            EmitKind::Mir => "--emit=mir",
+            EmitKind::Metadata => "--emit=metadata",
+        }
+    }
+
+    /// Extension of the emitted files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            EmitKind::Asm => "s",
+            EmitKind::LlvmIr => "ll",
+            EmitKind::Mir => "mir",
+            EmitKind::Metadata => "rmeta",
+        }
+    }
+
+    /// Prefix rustc gives the emitted files, before the crate name.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            EmitKind::Metadata => "lib",
+            _ => "",
+        }
     }
 }
 
-#[derive(Debug, StructOpt, Default)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DependencyKind {
+    Normal,
+    Dev,
+    Build,
+}
+
+impl DependencyKind {
+    /// Match a header prefix such as `dev` in `//# dev: serde = "1"`. Surrounding whitespace and
+    /// case are ignored.
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix.trim().to_ascii_lowercase().as_str() {
+            "dev" | "test" => Some(DependencyKind::Dev),
+            "build" => Some(DependencyKind::Build),
+            _ => None,
+        }
+    }
+}
+
+/// A dependency declared in a `//#` header, e.g. `serde = "1"` or `dev: criterion = "0.3"`.
+/// Prefixes compose, so `dev: cfg(unix): nix = "0.27"` is a dev-dependency for unix targets.
+#[derive(Debug, PartialEq)]
+pub struct Dependency {
+    pub kind: DependencyKind,
+    /// A `cfg(...)` expression restricting the dependency to matching targets.
+    pub target: Option<String>,
+    pub spec: String,
+}
+
+impl Dependency {
+    /// Split a leading `cfg(...):` prefix, with balanced parentheses, off `line`.
+    fn split_target(line: &str) -> Option<(&str, &str)> {
+        if !line.starts_with("cfg(") {
+            return None;
+        }
+
+        let mut depth = 0;
+        for (index, c) in line.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 1 => {
+                    let (target, rest) = line.split_at(index + 1);
+                    return rest
+                        .trim_start()
+                        .strip_prefix(':')
+                        .map(|rest| (target, rest));
+                }
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+impl FromStr for Dependency {
+    type Err = CargoPlayError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut kind = None;
+        let mut target = None;
+        let mut rest = line.trim();
+
+        loop {
+            if let Some((cfg, spec)) = Self::split_target(rest).filter(|_| target.is_none()) {
+                target = Some(cfg.to_string());
+                rest = spec.trim();
+                continue;
+            }
+
+            match rest.split_once(':') {
+                Some((prefix, spec)) if kind.is_none() => match DependencyKind::from_prefix(prefix)
+                {
+                    Some(prefix) => {
+                        kind = Some(prefix);
+                        rest = spec.trim();
+                    }
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+
+        match rest.split_once('=') {
+            Some((name, spec)) if !name.trim().is_empty() && !spec.trim().is_empty() => {
+                Ok(Dependency {
+                    kind: kind.unwrap_or(DependencyKind::Normal),
+                    target,
+                    spec: rest.into(),
+                })
+            }
+            _ => Err(CargoPlayError::InvalidDependency(format!(
+                "`{}`",
+                line.trim()
+            ))),
+        }
+    }
+}
+
+/// A `--profile-set dev.opt-level=1` option, setting `opt-level = 1` under `[profile.dev]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileSetting {
+    pub profile: String,
+    pub key: String,
+    pub value: toml::Value,
+}
+
+impl ProfileSetting {
+    /// Reject values of the wrong type for well known keys. Unknown keys are left for cargo to
+    /// validate.
+    fn check_type(key: &str, value: &toml::Value) -> bool {
+        use toml::Value;
+
+        match (key, value) {
+            ("opt-level", Value::Integer(level)) => (0..=3).contains(level),
+            ("opt-level", Value::String(level)) => level == "s" || level == "z",
+            ("debug", Value::Integer(level)) => (0..=2).contains(level),
+            ("codegen-units", Value::Integer(units)) => *units > 0,
+            ("panic", Value::String(strategy)) => strategy == "unwind" || strategy == "abort",
+            ("lto", Value::Boolean(_)) | ("lto", Value::String(_)) => true,
+            ("strip", Value::Boolean(_)) | ("strip", Value::String(_)) => true,
+            ("split-debuginfo", Value::String(_)) => true,
+            ("debug", Value::Boolean(_))
+            | ("debug-assertions", Value::Boolean(_))
+            | ("overflow-checks", Value::Boolean(_))
+            | ("incremental", Value::Boolean(_))
+            | ("rpath", Value::Boolean(_)) => true,
+            (
+                "opt-level" | "debug" | "codegen-units" | "panic" | "lto" | "strip"
+                | "split-debuginfo" | "debug-assertions" | "overflow-checks" | "incremental"
+                | "rpath",
+                _,
+            ) => false,
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for ProfileSetting {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason: &str| {
+            CargoPlayError::ParseError(format!("invalid profile setting {:?}: {}", s, reason))
+        };
+
+        let (path, raw) = s
+            .split_once('=')
+            .ok_or_else(|| error("expected profile.key=value"))?;
+        let (profile, key) = path
+            .trim()
+            .split_once('.')
+            .filter(|(profile, key)| !profile.is_empty() && !key.is_empty())
+            .ok_or_else(|| error("expected profile.key=value"))?;
+
+        // booleans, numbers and quoted strings keep their type, anything else is a bare string
+        let raw = raw.trim();
+        let value = toml::from_str::<toml::value::Table>(&format!("value = {}", raw))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(raw.into()));
+
+        if !Self::check_type(key, &value) {
+            return Err(error(&format!("unexpected value for `{}`", key)));
+        }
+
+        Ok(ProfileSetting {
+            profile: profile.into(),
+            key: key.into(),
+            value,
+        })
+    }
+}
+
+/// A `--env KEY=VALUE` option, setting an environment variable for cargo and the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for EnvVar {
+    type Err = CargoPlayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .filter(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace))
+            .ok_or_else(|| {
+                CargoPlayError::ParseError(format!(
+                    "invalid environment variable {:?}: expected KEY=VALUE",
+                    s
+                ))
+            })?;
+
+        Ok(EnvVar {
+            name: name.into(),
+            value: value.into(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, StructOpt, Default)]
 #[structopt(
     name = "cargo-play",
-    about = "Run your Rust program without Cargo.toml"
+    about = "Run your Rust program without Cargo.toml",
+    raw(group = "batch_group()")
 )]
 pub struct Opt {
     #[structopt(short = "d", long = "debug", hidden = true)]
+    /// Show cargo-play's debug messages, whatever RUST_LOG says
     pub debug: bool,
     #[structopt(short = "c", long = "clean")]
     /// Rebuild the cargo project without the cache from previous run
     pub clean: bool,
+    #[structopt(long = "check-only-changed", conflicts_with = "concat")]
+    /// Only copy the sources whose content changed since the previous run, so cargo rebuilds as
+    /// little as possible, and remove the copies of sources no longer given. Edited sources keep
+    /// their project instead of getting a new one.
+    pub check_only_changed: bool,
+    #[structopt(long = "fast", conflicts_with = "no_cache")]
+    /// Tune the build for edit-run speed: `opt-level = 0` and `incremental = true` in
+    /// [profile.dev], `-C debuginfo=0` in RUSTFLAGS, and a target directory shared by all
+    /// snippets unless CARGO_TARGET_DIR is set
+    pub fast: bool,
+    #[structopt(
+        long = "target-dir",
+        parse(from_os_str),
+        raw(conflicts_with_all = r#"&["no_cache", "scratch", "container"]"#)
+    )]
+    /// Directory for the build artifacts, taking precedence over CARGO_TARGET_DIR. Snippets
+    /// sharing one reuse the dependencies built for each other.
+    pub target_dir: Option<PathBuf>,
+    #[structopt(long = "no-cache", conflicts_with = "cached")]
+    /// Build from scratch in a new temporary project that is removed afterwards, leaving the
+    /// cache of previous runs untouched. With --clean, that cache is removed as well.
+    pub no_cache: bool,
+    #[structopt(
+        long = "scratch",
+        raw(conflicts_with_all = r#"&["cached", "fast", "save"]"#)
+    )]
+    /// Like --no-cache, with the project on a memory-backed filesystem (/dev/shm) when there is
+    /// one, so no build artifact ever reaches the disk
+    pub scratch: bool,
     #[structopt(short = "t", long = "toolchain", hidden = true)]
     pub toolchain: Option<String>,
     #[structopt(
-        parse(try_from_os_str = "osstr_to_abspath"),
-        raw(required = "true", validator = "file_exist")
+        parse(try_from_os_str = "osstr_to_source"),
+        raw(required = "true", validator = "source_exist")
     )]
-    /// Paths to your source code files
+    /// Paths to your source code files, or to the directory of an existing Cargo project. `-`
+    /// reads a single source file from stdin.
     pub src: Vec<PathBuf>,
     #[structopt(
         short = "e",
         long = "edition",
-        default_value = "2018",
-        raw(possible_values = r#"&["2015", "2018"]"#)
+        raw(possible_values = r#"&["2015", "2018", "2021", "2024"]"#)
+    )]
+    /// Specify Rust edition [default: 2021], overriding any `//# edition` header
+    pub edition: Option<RustEdition>,
+    #[structopt(long = "enforce-rust-version")]
+    /// Fail before building when the toolchain is older than the `//# rust-version` of the source
+    pub enforce_rust_version: bool,
+    #[structopt(long = "resolver", raw(possible_values = r#"&["1", "2", "3"]"#))]
+    /// Set the dependency resolver of the generated package, which otherwise follows the edition
+    pub resolver: Option<String>,
+    #[structopt(long = "concat")]
+    /// Concatenate all source files, in order, into a single crate root
+    pub concat: bool,
+    #[structopt(
+        long = "separate",
+        raw(
+            conflicts_with_all = r#"&["concat", "main", "name", "save", "emit_manifest", "clipboard", "output_format"]"#
+        )
+    )]
+    /// Run every source file as a snippet of its own, one after the other, stopping at the first
+    /// that fails, and summarize the results
+    pub separate: bool,
+    #[structopt(
+        long = "workspace",
+        raw(
+            conflicts_with_all = r#"&["concat", "main", "name", "lib", "save", "emit_manifest", "clipboard", "output_format", "cached", "no_cache", "run_count", "debug_run"]"#
+        )
+    )]
+    /// Generate a workspace with a member package for every source file, named after the file,
+    /// and build it without running anything. Sources without a `fn main` are libraries, which
+    /// the others use with headers such as `//# helper = { workspace = true }`.
+    pub workspace: bool,
+    #[structopt(long = "keep-going", raw(requires = r#""batch""#))]
+    /// With --separate, run the remaining sources after one fails, the exit code being the one
+    /// of the first failure. With --workspace, build the remaining members after one fails.
+    pub keep_going: bool,
+    #[structopt(long = "main-fn", conflicts_with = "lib")]
+    /// Run the given function of the first source file through a generated `fn main`, printing
+    /// its return value with `{:?}` unless it returns `()`
+    pub main_fn: Option<String>,
+    #[structopt(
+        long = "run-as-test",
+        raw(
+            conflicts_with_all = r#"&["lib", "main_fn", "test_files", "emit", "expand", "run_count"]"#
+        )
     )]
-    /// Specify Rust edition
-    pub edition: RustEdition,
+    /// Run the first source file as a `#[test]` calling its `fn main`, or wrapping its statements
+    /// when it has none, so failed assertions are reported by the test harness
+    pub run_as_test: bool,
+    #[structopt(long = "lib")]
+    /// Build the first source file as a library (src/lib.rs) and run its tests
+    pub lib: bool,
+    #[structopt(
+        long = "bench",
+        raw(
+            conflicts_with_all = r#"&["run_as_test", "emit", "expand", "run_count", "debug_run"]"#
+        )
+    )]
+    /// Run `cargo bench` instead of the program. Without `#[bench]` functions, which need a
+    /// nightly toolchain, `fn main` is the benchmark, e.g. the one of `criterion_main!`
+    pub bench: bool,
+    #[structopt(
+        long = "main",
+        parse(try_from_os_str = "osstr_to_abspath"),
+        raw(
+            validator = "file_exist",
+            requires = r#""lib""#,
+            conflicts_with = r#""concat""#
+        )
+    )]
+    /// With --lib, a binary (src/main.rs) using the library, which is run instead of the tests
+    pub main: Option<PathBuf>,
+    #[structopt(
+        long = "test-file",
+        parse(try_from_os_str = "osstr_to_abspath"),
+        raw(validator = "file_exist", number_of_values = "1")
+    )]
+    /// Integration test files placed under tests/ and run with `cargo test`
+    pub test_files: Vec<PathBuf>,
+    #[structopt(long = "bin")]
+    /// Name of the binary to run, when several are declared with `//# bin:` headers
+    pub bin: Option<String>,
     #[structopt(long = "release")]
     /// Build program in release mode
     pub release: bool,
+    #[structopt(long = "target")]
+    /// Build for the given target triple instead of the host. Running the binary needs a runner
+    /// configured for cargo, building with e.g. --check or --save works without one.
+    pub target: Option<String>,
+    #[structopt(long = "offline")]
+    /// Pass --offline to cargo, building without accessing the network
+    pub offline: bool,
+    #[structopt(long = "frozen")]
+    /// Pass --frozen to cargo, requiring Cargo.lock and the cache to be up to date
+    pub frozen: bool,
+    #[structopt(long = "locked")]
+    /// Pass --locked to cargo, requiring Cargo.lock to be up to date
+    pub locked: bool,
     #[structopt(long = "cached", hidden = true)]
+    /// Run the previously built binary when none of the sources were modified after it. Only
+    /// modification times are compared, so this is cheap but misses changes that keep an older
+    /// mtime, e.g. files restored from a backup or switched to another git branch.
     pub cached: bool,
-    #[structopt(long = "cargo-option")]
+    #[structopt(long = "arg0")]
+    /// [unix] Program name the binary sees as `argv[0]` when cargo-play runs it directly, with
+    /// --cached or --run-count, instead of its path in the temporary project
+    pub arg0: Option<String>,
+    #[structopt(short = "q", long = "quiet")]
+    /// Pass --quiet to cargo, hiding its build output while the program's own output still comes
+    /// through
+    pub quiet: bool,
+    #[structopt(long = "quiet-on-success")]
+    /// Only show cargo's build output when the build fails
+    pub quiet_on_success: bool,
+    #[structopt(long = "no-interactive")]
+    /// Show cargo's build output even when stdout is a terminal, where runs default to
+    /// --quiet-on-success
+    pub no_interactive: bool,
+    #[structopt(
+        long = "emit",
+        raw(possible_values = r#"&["asm", "llvm-ir", "mir", "metadata"]"#)
+    )]
+    /// Build without running and print the paths of the emitted asm/llvm-ir/mir/rmeta files,
+    /// found under target/<profile>/deps of the temporary project. Changing it forces a rebuild.
+    /// `--emit-metadata` is short for `--emit metadata`.
+    pub emit: Option<EmitKind>,
+    #[structopt(long = "cfg", raw(validator = "cfg_valid", number_of_values = "1"))]
+    /// Set a `name` or `name=value` cfg for conditional compilation, e.g. `--cfg feature=foo`.
+    /// Can be repeated.
+    pub cfg: Vec<String>,
+    #[structopt(long = "ensure-components", raw(require_delimiter = "true"))]
+    /// Install the given comma-separated rustup components (e.g. `rust-src`) before building
+    pub ensure_components: Vec<String>,
+    #[structopt(long = "ensure-targets", raw(require_delimiter = "true"))]
+    /// Install the given comma-separated rustup targets (e.g. `wasm32-unknown-unknown`) before
+    /// building
+    pub ensure_targets: Vec<String>,
+    #[structopt(long = "embed-version")]
+    /// Expose the source hash and the cargo-play version to the program as the
+    /// `CARGO_PLAY_SRC_HASH` and `CARGO_PLAY_VERSION` compile-time environment variables
+    pub embed_version: bool,
+    #[structopt(
+        long = "check",
+        raw(
+            conflicts_with_all = r#"&["clippy", "bench", "run_as_test", "emit", "expand", "run_count", "debug_run", "out_dir"]"#
+        )
+    )]
+    /// Only type-check the sources with `cargo check`, without building or running them
+    pub check: bool,
+    #[structopt(
+        long = "clippy",
+        raw(
+            conflicts_with_all = r#"&["bench", "run_as_test", "emit", "expand", "run_count", "debug_run", "out_dir"]"#
+        )
+    )]
+    /// Lint the sources with `cargo clippy` instead of running them. Arguments after `--` are
+    /// passed to clippy, e.g. `-- -D warnings`.
+    pub clippy: bool,
+    #[structopt(long = "expand", conflicts_with = "emit")]
+    /// Print the sources after macro expansion with `cargo expand` instead of running them. Use
+    /// --cargo-option to expand a single item.
+    pub expand: bool,
+    #[structopt(long = "profile-set", raw(number_of_values = "1"))]
+    /// Set a key of a [profile.*] table of the generated manifest, e.g. `dev.opt-level=1`. Can be
+    /// repeated.
+    pub profile_set: Vec<ProfileSetting>,
+    #[structopt(long = "panic", raw(possible_values = r#"&["unwind", "abort"]"#))]
+    /// Set the panic strategy of the dev and release profiles. Tests always unwind.
+    pub panic: Option<PanicStrategy>,
+    #[structopt(
+        long = "allocator",
+        raw(possible_values = r#"&["jemalloc", "mimalloc", "system"]"#)
+    )]
+    /// Use the given global allocator, adding its dependency and a `#[global_allocator]` static.
+    /// Skipped when the source defines its own.
+    pub allocator: Option<Allocator>,
+    #[structopt(
+        long = "run-count",
+        raw(
+            validator = "positive_count",
+            conflicts_with_all = r#"&["lib", "test_files", "emit", "expand", "save", "pty"]"#
+        )
+    )]
+    /// Build once, then run the binary the given number of times and report the timings
+    pub run_count: Option<usize>,
+    #[structopt(
+        long = "debug-run",
+        raw(
+            conflicts_with_all = r#"&["release", "lib", "test_files", "emit", "expand", "save", "run_count"]"#
+        )
+    )]
+    /// Build, then start the binary under rust-gdb or rust-lldb, whichever debugger is installed
+    pub debug_run: bool,
+    #[structopt(long = "run-all", raw(requires = r#""run_count""#))]
+    /// With --run-count, keep running after a run exits with a non-zero code
+    pub run_all: bool,
+    #[structopt(long = "cargo-option", raw(validator = "cargo_option_valid"))]
     /// Custom flags passing to cargo
     pub cargo_option: Option<String>,
     #[structopt(long = "save")]
     /// Generate a Cargo project based on inputs
     pub save: Option<PathBuf>,
+    #[structopt(
+        long = "out-dir",
+        parse(from_os_str),
+        raw(conflicts_with_all = r#"&["save", "expand"]"#)
+    )]
+    /// Copy the built binary, or the library with --lib, into the given directory
+    pub out_dir: Option<PathBuf>,
+    #[structopt(long = "strip", raw(requires = r#""out_dir""#))]
+    /// Strip symbols from the binary exported with --out-dir. Only applies to --release builds.
+    pub strip: bool,
+    #[structopt(long = "emit-manifest")]
+    /// Write only the generated Cargo.toml to the given path and exit
+    pub emit_manifest: Option<PathBuf>,
+    #[structopt(long = "clipboard")]
+    /// Copy the generated Cargo.toml to the clipboard and exit. Prints it when no clipboard tool
+    /// is available.
+    pub clipboard: bool,
+    #[structopt(
+        long = "manifest-format",
+        default_value = "toml",
+        raw(possible_values = r#"&["toml", "pretty"]"#)
+    )]
+    /// Format of the generated Cargo.toml: `toml`, or `pretty` to make emitted and saved
+    /// manifests look hand-written
+    pub manifest_format: ManifestFormat,
+    #[structopt(
+        long = "hash",
+        default_value = "sha1",
+        raw(possible_values = r#"&["sha1", "sha256"]"#)
+    )]
+    /// Digest naming the temporary project and the package, taken over the paths of the sources
+    /// and, unless --check-only-changed, their contents, the toolchain, the `--cfg`s and the
+    /// `--allocator`
+    pub hash: HashAlgorithm,
+    #[structopt(long = "hash-length", raw(validator = "hash_length_valid"))]
+    /// Keep only the first N characters of the base64 hash, at least 4, for shorter temporary
+    /// directory names. Each character holds 6 bits, and snippets with the same cut hash share a
+    /// project, rebuilding over each other: with 4 characters, a collision becomes likely around
+    /// 5000 cached snippets.
+    pub hash_length: Option<usize>,
+    #[structopt(long = "name", raw(validator = "package_name_valid"))]
+    /// Name of the generated package and of its binary, instead of one derived from the hash
+    pub name: Option<String>,
+    #[structopt(
+        long = "output-format",
+        default_value = "human",
+        raw(possible_values = r#"&["human", "json"]"#)
+    )]
+    /// Report the outcome as a JSON object on stdout with `json`: the stage it ended in
+    /// (`setup`, `build` or `run`), the exit code and, on failure, the kind of error
+    pub output_format: OutputFormat,
+    #[structopt(
+        long = "manifest-include",
+        parse(try_from_os_str = "osstr_to_abspath"),
+        raw(validator = "file_exist")
+    )]
+    /// Merge the [dependencies], [dev-dependencies] and [build-dependencies] sections of a TOML
+    /// file into the generated manifest. Headers take precedence.
+    pub manifest_include: Option<PathBuf>,
+    #[structopt(
+        long = "record-manifest",
+        raw(max_values = "1", require_equals = "true")
+    )]
+    /// Record the path of the generated manifest as `CARGO_PLAY_MANIFEST=<path>` for editor
+    /// integrations, in `.cargo-play-last` in the current directory or `--record-manifest=<file>`
+    pub record_manifest: Option<Option<PathBuf>>,
+    #[structopt(
+        long = "from-project",
+        parse(try_from_os_str = "osstr_to_abspath"),
+        raw(validator = "manifest_dir_exist")
+    )]
+    /// Use the dependencies (and Cargo.lock) of an existing Cargo project
+    pub from_project: Option<PathBuf>,
+    #[structopt(long = "print-target-dir")]
+    /// Print the target directory the build artifacts go to and exit without building
+    pub print_target_dir: bool,
+    #[structopt(long = "print-deps")]
+    /// Print the resolved versions of all dependencies after the build
+    pub print_deps: bool,
+    #[structopt(long = "trace-cargo")]
+    /// Print the dependency tree with the features cargo enabled on each crate after the build
+    pub trace_cargo: bool,
+    #[structopt(long = "sandbox")]
+    /// Wrapper command (e.g. `firejail`) used to run cargo and the program
+    pub sandbox: Option<String>,
+    #[structopt(
+        long = "container",
+        raw(
+            conflicts_with_all = r#"&["sandbox", "cached", "run_count", "debug_run", "fast", "pty"]"#
+        )
+    )]
+    /// Build and run in a container of the given image, which needs a Rust toolchain, with
+    /// docker or podman. The temporary project is mounted at the same path.
+    pub container: Option<String>,
+    #[structopt(
+        long = "env-file",
+        parse(try_from_os_str = "osstr_to_abspath"),
+        raw(validator = "file_exist")
+    )]
+    /// Load environment variables for the program from a file of `KEY=VALUE` lines
+    pub env_file: Option<PathBuf>,
+    #[structopt(long = "env", raw(number_of_values = "1"))]
+    /// Set an environment variable for cargo and the program, e.g. `RUST_BACKTRACE=1`. Can be
+    /// repeated, and wins over --env-file.
+    pub env: Vec<EnvVar>,
+    #[structopt(long = "rustflags")]
+    /// Extra flags passed to rustc through RUSTFLAGS, e.g. `-C target-cpu=native`
+    pub extra_rustflags: Option<String>,
+    #[structopt(long = "on-success")]
+    /// Shell command run after a successful build and run, with the binary and the temporary
+    /// project in the `CARGO_PLAY_BINARY` and `CARGO_PLAY_PROJECT` environment variables
+    pub on_success: Option<String>,
+    #[structopt(long = "explain-error")]
+    /// Print hints for common mistakes when the build fails
+    pub explain_error: bool,
+    #[structopt(long = "pre-expand-macros")]
+    /// Before building, check that the crates of the derive and attribute macros used, such as
+    /// `#[derive(Serialize)]`, are declared with the features providing them
+    pub pre_expand_macros: bool,
+    #[structopt(long = "max-deps")]
+    /// Fail when the snippet has more dependencies than this, counting inferred ones, to catch
+    /// --infer picking up crates that aren't meant to be dependencies
+    pub max_deps: Option<usize>,
+    #[structopt(long = "explain-deps")]
+    /// Print where each dependency is declared: `//#` headers take precedence over `.deps`
+    /// sidecars, then --manifest-include, then --from-project, then --infer
+    pub explain_deps: bool,
+    #[structopt(long = "wrap-errors")]
+    /// Exit with 64 on usage errors and 65 on build failures, and with the program's own exit code
+    /// otherwise
+    pub wrap_errors: bool,
+    #[structopt(long = "log-file", parse(from_os_str))]
+    /// Also write cargo-play's own messages (warnings, hints, timings) to a file
+    pub log_file: Option<PathBuf>,
+    #[structopt(long = "pty")]
+    /// [unix] Attach the program's stdout to a pseudo-terminal, even when piped
+    pub pty: bool,
     /// [experimental] Automatically infers dependency
     #[structopt(long = "infer", short = "i")]
     pub infer: bool,
+    #[structopt(
+        long = "infer-level",
+        default_value = "aggressive",
+        raw(possible_values = r#"&["conservative", "aggressive"]"#)
+    )]
+    /// With --infer, `aggressive` takes every `use` as a crate, `conservative` skips lone
+    /// `use name;` imports and the crate's own modules
+    pub infer_level: InferLevel,
+    #[structopt(
+        long = "infer-version-style",
+        default_value = "wildcard",
+        raw(possible_values = r#"&["wildcard", "caret", "exact"]"#)
+    )]
+    /// With --infer, write inferred versions as `*`, or as `^x.y` or `=x.y.z` of the latest
+    /// version on crates.io
+    pub infer_version_style: VersionStyle,
+    #[structopt(long = "index")]
+    /// Registry index the latest versions of --infer-version-style are looked up in, e.g. a
+    /// mirror of crates.io. Cargo's own builds keep using its configuration.
+    pub index: Option<String>,
     #[structopt(multiple = true, last = true)]
     /// Arguments passed to the underlying program
     pub args: Vec<String>,
 }
 
+/// The package name cargo is given for `name`, which is also the name of the binary it builds.
+/// Cargo rejects names that start with a digit or a dash, which the source hash does now and
+/// then.
+pub fn package_name(name: &str) -> String {
+    let name = name.to_lowercase();
+
+    if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name
+    } else {
+        format!("_{}", name)
+    }
+}
+
 impl Opt {
     #[allow(unused)]
     /// Convenient constructor for testing
@@ -101,30 +1066,213 @@ impl Opt {
         }
     }
 
-    /// Generate a string of hash based on the path passed in
+    /// Generate a string of hash based on the paths and the contents of the sources, with the
+    /// `--hash` digest cut to `--hash-length`. Every name derived from the sources goes through
+    /// here, so they agree.
     pub fn src_hash(&self) -> String {
-        let mut hash = sha1::Sha1::new();
+        let mut input = Vec::new();
         let mut srcs = self.src.clone();
 
         srcs.sort();
 
         for file in srcs.into_iter() {
-            hash.update(file.to_string_lossy().as_bytes());
+            input.extend_from_slice(file.to_string_lossy().as_bytes());
+
+            // an edited source, or its `//#` headers, gets a project of its own instead of
+            // reusing the binary of the previous version, unless the project is meant to be
+            // updated in place; unreadable ones are reported later
+            if self.check_only_changed {
+                continue;
+            }
+            if let Ok(content) = std::fs::read(&file) {
+                input.push(0);
+                input.extend_from_slice(&content);
+            }
+        }
+
+        // binaries built by different toolchains must not be mistaken for one another
+        if let Some(toolchain) = &self.toolchain {
+            input.extend_from_slice(b"+");
+            input.extend_from_slice(toolchain.as_bytes());
+        }
+
+        // as are binaries built with different cfgs, which `--cached` can't tell apart
+        let mut cfgs = self.cfg.clone();
+        cfgs.sort();
+        for cfg in cfgs {
+            input.extend_from_slice(b"--cfg");
+            input.extend_from_slice(cfg.as_bytes());
+        }
+
+        if let Some(allocator) = self.allocator {
+            input.extend_from_slice(format!("--allocator{:?}", allocator).as_bytes());
+        }
+
+        let mut hash = base64::encode_config(&self.hash.digest(&input), base64::URL_SAFE_NO_PAD);
+        if let Some(length) = self.hash_length {
+            hash.truncate(length);
+        }
+        hash
+    }
+
+    /// Directory of the existing Cargo project given instead of source files, which is built as
+    /// it is rather than with a generated manifest.
+    pub fn existing_project(&self) -> Option<&Path> {
+        self.src
+            .first()
+            .map(PathBuf::as_path)
+            .filter(|src| src.is_dir())
+    }
+
+    /// Name of the generated package, given with `--name` or derived from the source hash.
+    pub fn package_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => package_name(&self.src_hash()),
         }
+    }
 
-        base64::encode_config(&hash.digest().bytes()[..], base64::URL_SAFE_NO_PAD)
+    /// Action to perform on the generated project. Libraries and integration tests have nothing
+    /// to run, so their tests are executed instead.
+    pub fn cargo_action(&self) -> CargoAction {
+        if self.expand {
+            CargoAction::Expand
+        } else if self.emit.is_some() {
+            CargoAction::Build
+        } else if self.check {
+            CargoAction::Check
+        } else if self.clippy {
+            CargoAction::Clippy
+        } else if self.bench {
+            CargoAction::Bench
+        } else if (self.lib && self.main.is_none())
+            || !self.test_files.is_empty()
+            || self.run_as_test
+        {
+            CargoAction::Test
+        } else {
+            CargoAction::Run
+        }
+    }
+
+    /// Extra flags passed to rustc through `RUSTFLAGS`, on top of the ones set in the
+    /// environment.
+    pub fn rustflags(&self) -> Option<String> {
+        let mut flags: Vec<String> = std::env::var("RUSTFLAGS").into_iter().collect();
+        flags.extend(self.extra_rustflags.clone());
+
+        if let Some(emit) = self.emit {
+            flags.push(emit.rustc_flag().into());
+        }
+
+        for cfg in &self.cfg {
+            flags.push(format!("--cfg {}", cfg_flag(cfg)));
+        }
+
+        if self.fast {
+            flags.push("-C debuginfo=0".into());
+        }
+
+        if flags.is_empty() {
+            None
+        } else {
+            Some(flags.join(" "))
+        }
+    }
+
+    /// Warnings about cargo flags in `--cargo-option` that have a dedicated cargo-play option.
+    pub fn cargo_option_warnings(&self) -> Vec<String> {
+        let tokens: Vec<&str> = self
+            .cargo_option
+            .iter()
+            .flat_map(|option| option.split_ascii_whitespace())
+            .collect();
+
+        DEDICATED_CARGO_FLAGS
+            .iter()
+            .filter(|flag| tokens.iter().any(|token| is_flag(token, flag)))
+            .map(|flag| {
+                format!(
+                    "prefer cargo-play's own {} over passing it through --cargo-option",
+                    flag
+                )
+            })
+            .collect()
+    }
+
+    /// All the source files of the crate, including the `--main` binary.
+    pub fn sources(&self) -> Vec<PathBuf> {
+        self.src.iter().chain(&self.main).cloned().collect()
+    }
+
+    /// Crate name of the library built with `--lib`, derived from the first source file.
+    pub fn lib_name(&self) -> Option<String> {
+        if !self.lib {
+            return None;
+        }
+
+        self.src
+            .first()
+            .and_then(|file| file.file_stem())
+            .map(|stem| stem.to_string_lossy().replace("-", "_"))
+    }
+
+    /// Whether the source is read from stdin, see `STDIN`.
+    pub fn reads_stdin(&self) -> bool {
+        self.src.iter().any(|src| src == Path::new(STDIN))
+    }
+
+    /// Directory keeping the source read from stdin, named after its content so the same input
+    /// is found again by the next run.
+    pub fn stdin_dirname(&self, source: &[u8]) -> PathBuf {
+        let hash = base64::encode_config(&self.hash.digest(source), base64::URL_SAFE_NO_PAD);
+        format!("cargo-play-stdin.{}", hash).into()
     }
 
     pub fn temp_dirname(&self) -> PathBuf {
         format!("cargo-play.{}", self.src_hash()).into()
     }
 
+    /// Settings of the `--fast` preset, applied before the `--profile-set` ones.
+    pub fn fast_profile(&self) -> Vec<ProfileSetting> {
+        if !self.fast {
+            return Vec::new();
+        }
+
+        vec![
+            ProfileSetting {
+                profile: "dev".into(),
+                key: "opt-level".into(),
+                value: toml::Value::Integer(0),
+            },
+            ProfileSetting {
+                profile: "dev".into(),
+                key: "incremental".into(),
+                value: toml::Value::Boolean(true),
+            },
+        ]
+    }
+
+    /// Name of the one-off project of `--no-cache`, unique to this process.
+    pub fn scratch_dirname(&self) -> PathBuf {
+        format!(
+            "cargo-play.{}.no-cache.{}",
+            self.src_hash(),
+            std::process::id()
+        )
+        .into()
+    }
+
+    /// Apply a `+toolchain` argument, which takes precedence over `--toolchain`.
     fn with_toolchain(mut self, toolchain: Option<String>) -> Self {
-        self.toolchain = toolchain;
+        if toolchain.is_some() {
+            self.toolchain = toolchain;
+        }
         self
     }
 
-    pub fn parse(args: Vec<String>) -> Result<Self, ()> {
+    #[allow(clippy::result_unit_err)]
+    pub fn parse(args: Vec<String>) -> Result<Invocation, ()> {
         if args.len() < 2 {
             Self::clap().print_help().unwrap_or(());
             return Err(());
@@ -137,12 +1285,236 @@ impl Opt {
             args.next();
         }
 
-        let toolchain = args
-            .clone()
-            .find(|x| x.starts_with('+'))
+        match args.clone().nth(1).as_deref() {
+            Some("list-cache") => return Ok(Invocation::ListCache),
+            Some("--list-editions") => return Ok(Invocation::ListEditions),
+            Some("--list-toolchains") => return Ok(Invocation::ListToolchains),
+            Some(arg) if arg == "--version-of" || arg.starts_with("--version-of=") => {
+                let name = match arg.split_once('=') {
+                    Some((_, name)) => Some(name.to_string()),
+                    None => args.clone().nth(2),
+                };
+                match name {
+                    Some(name) if !name.is_empty() && !name.starts_with('-') => {
+                        return Ok(Invocation::VersionOf(name))
+                    }
+                    _ => clap::Error::with_description(
+                        "--version-of requires the name of a crate",
+                        ErrorKind::EmptyValue,
+                    )
+                    .exit(),
+                }
+            }
+            Some("new") => {
+                let args = std::iter::once(String::from("cargo play new")).chain(args.skip(2));
+                return Ok(Invocation::New(NewOpt::from_iter(args)));
+            }
+            _ => {}
+        }
+
+        let args = expand_shebang_args(args.collect()).into_iter();
+
+        // a `+toolchain` argument can only appear before the program arguments
+        let mut before_program_args = true;
+        let (toolchains, args): (Vec<String>, Vec<String>) = args.partition(|x| {
+            before_program_args &= x != "--";
+            before_program_args && x.starts_with('+')
+        });
+        let toolchain = toolchains
+            .into_iter()
+            .next()
             .map(|s| String::from_iter(s.chars().skip(1)));
 
-        Ok(Opt::from_iter(args.filter(|x| !x.starts_with('+'))).with_toolchain(toolchain))
+        // `--emit-metadata` is an alias, only among cargo-play's own arguments
+        let mut before_program_args = true;
+        let args: Vec<String> = args
+            .into_iter()
+            .map(|arg| {
+                before_program_args &= arg != "--";
+                if before_program_args && arg == "--emit-metadata" {
+                    "--emit=metadata".into()
+                } else {
+                    arg
+                }
+            })
+            .collect();
+
+        let wrap_errors = args.iter().any(|arg| arg == "--wrap-errors");
+        let opt = Opt::from_iter_safe(args).unwrap_or_else(|e| match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            _ if wrap_errors => {
+                eprintln!("{}", e.message);
+                std::process::exit(EXIT_USAGE)
+            }
+            _ => e.exit(),
+        });
+
+        Ok(Invocation::Play(Box::new(opt.with_toolchain(toolchain))))
+    }
+}
+
+/// When run as a script interpreter through a shebang such as `#!/usr/bin/cargo-play --release
+/// --quiet-on-success`, the kernel passes all the flags of the shebang as a single argument,
+/// followed by the script. Split them back into separate arguments, but only when the script's
+/// shebang really is the origin of that argument.
+fn expand_shebang_args(args: Vec<String>) -> Vec<String> {
+    let from_shebang = match (args.get(1), args.get(2)) {
+        (Some(flags), Some(script)) if flags.starts_with('-') && flags.contains(' ') => {
+            std::fs::read_to_string(script)
+                .ok()
+                .and_then(|source| source.lines().next().map(String::from))
+                .is_some_and(|line| line.starts_with("#!") && line.trim_end().ends_with(flags))
+        }
+        _ => false,
+    };
+
+    if !from_shebang {
+        return args;
+    }
+
+    let mut args = args.into_iter();
+    let program = args.next();
+    let flags = args.next().unwrap_or_default();
+
+    program
+        .into_iter()
+        .chain(flags.split_whitespace().map(String::from))
+        .chain(args)
+        .collect()
+}
+
+/// What to do, as parsed from the command line.
+#[derive(Debug)]
+pub enum Invocation {
+    /// Build and run the given sources.
+    Play(Box<Opt>),
+    /// `cargo play list-cache`: list the cached projects of previous runs.
+    ListCache,
+    /// `cargo play new`: create a snippet from a template.
+    New(NewOpt),
+    /// `cargo play --version-of <crate>`: print the latest version of a crate.
+    VersionOf(String),
+    /// `cargo play --list-editions`: print the supported editions.
+    ListEditions,
+    /// `cargo play --list-toolchains`: print the installed rustup toolchains.
+    ListToolchains,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "cargo-play-new",
+    about = "Create a new snippet from a template"
+)]
+pub struct NewOpt {
+    #[structopt(
+        long = "template",
+        default_value = "hello",
+        raw(possible_values = "templates::NAMES")
+    )]
+    /// Kind of snippet to start from
+    pub template: String,
+    #[structopt(parse(from_os_str))]
+    /// Path of the snippet to create
+    pub path: PathBuf,
+}
+
+/// Cargo flags cargo-play sets itself, which can't be overridden through `--cargo-option`.
+const MANAGED_CARGO_FLAGS: &[(&str, &str)] = &[
+    (
+        "--manifest-path",
+        "the manifest of the generated project is managed by cargo-play",
+    ),
+    (
+        "--target-dir",
+        "cargo-play looks up build artifacts in the project's target directory",
+    ),
+];
+
+/// Cargo flags with a dedicated cargo-play option, which should be preferred.
+const DEDICATED_CARGO_FLAGS: &[&str] = &["--release", "--bin"];
+
+/// Whether the `--cargo-option` token is the given flag, in either `--flag value` or
+/// `--flag=value` form.
+fn is_flag(token: &str, flag: &str) -> bool {
+    token == flag
+        || token
+            .strip_prefix(flag)
+            .is_some_and(|value| value.starts_with('='))
+}
+
+fn cargo_option_valid(v: String) -> Result<(), String> {
+    for token in v.split_ascii_whitespace() {
+        for (flag, reason) in MANAGED_CARGO_FLAGS {
+            if is_flag(token, flag) {
+                return Err(format!("{} can't be passed to cargo: {}", flag, reason));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The modes handling every source on its own, which `--keep-going` applies to.
+fn batch_group() -> clap::ArgGroup<'static> {
+    clap::ArgGroup::with_name("batch").args(&["separate", "workspace"])
+}
+
+/// structopt compatible function to check a `--hash-length` keeps enough of the hash
+fn hash_length_valid(v: String) -> Result<(), String> {
+    match v.parse::<usize>() {
+        Ok(length) if length >= 4 => Ok(()),
+        _ => Err(format!("invalid hash length {:?}, expected at least 4", v)),
+    }
+}
+
+/// structopt compatible function to check a `--name` is a package name cargo accepts, kept
+/// lowercase like the derived ones
+fn package_name_valid(v: String) -> Result<(), String> {
+    if v.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && v.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid package name {:?}, expected lowercase letters, digits, `-` and `_`, not \
+             starting with a digit or `-`",
+            v
+        ))
+    }
+}
+
+/// structopt compatible function to check a `--cfg` is a `name` or `name=value` pair that can
+/// be passed through `RUSTFLAGS`
+fn cfg_valid(v: String) -> Result<(), String> {
+    let (name, value) = match v.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (v.as_str(), None),
+    };
+    let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if !identifier {
+        Err(format!("invalid cfg name {:?}", name))
+    } else if value.is_some_and(|value| value.is_empty() || value.contains(char::is_whitespace)) {
+        Err(format!("invalid value in cfg {:?}", v))
+    } else {
+        Ok(())
+    }
+}
+
+/// The rustc form of a `--cfg`, with its value quoted.
+fn cfg_flag(cfg: &str) -> String {
+    match cfg.split_once('=') {
+        Some((name, value)) => format!("{}=\"{}\"", name, value.trim_matches('"')),
+        None => cfg.to_string(),
+    }
+}
+
+fn positive_count(v: String) -> Result<(), String> {
+    match v.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(()),
+        _ => Err(format!("expected a positive number, found {:?}", v)),
     }
 }
 
@@ -164,3 +1536,389 @@ fn file_exist(v: String) -> Result<(), String> {
         Ok(())
     }
 }
+
+/// Like `osstr_to_abspath`, keeping `-` for stdin as it is.
+fn osstr_to_source(v: &OsStr) -> Result<PathBuf, OsString> {
+    if v == STDIN {
+        Ok(PathBuf::from(v))
+    } else {
+        osstr_to_abspath(v)
+    }
+}
+
+/// structopt compataible function to check whether a source file, or a directory containing a
+/// Cargo project, exists
+fn source_exist(v: String) -> Result<(), String> {
+    if v == STDIN {
+        Ok(())
+    } else if PathBuf::from(&v).is_dir() {
+        manifest_dir_exist(v)
+    } else {
+        file_exist(v)
+    }
+}
+
+/// structopt compataible function to check whether a directory contains a Cargo project
+fn manifest_dir_exist(v: String) -> Result<(), String> {
+    let p = PathBuf::from(v).join("Cargo.toml");
+    if !p.is_file() {
+        Err(format!("Cargo.toml does not exist: {:?}", p))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency_prefix() {
+        let normal = r#"serde = "1""#.parse::<Dependency>().unwrap();
+        assert_eq!(normal.kind, DependencyKind::Normal);
+        assert_eq!(normal.spec, r#"serde = "1""#);
+
+        for line in &[
+            r#"dev: serde = "1""#,
+            r#"dev : serde = "1""#,
+            r#"DEV:serde = "1""#,
+            r#"  dev:   serde = "1""#,
+            r#"test: serde = "1""#,
+        ] {
+            let dependency = line.parse::<Dependency>().unwrap();
+            assert_eq!(dependency.kind, DependencyKind::Dev, "{}", line);
+            assert_eq!(dependency.spec, r#"serde = "1""#, "{}", line);
+        }
+
+        let build = r#" Build : cc = "1.0""#.parse::<Dependency>().unwrap();
+        assert_eq!(build.kind, DependencyKind::Build);
+        assert_eq!(build.spec, r#"cc = "1.0""#);
+    }
+
+    #[test]
+    fn test_dependency_short_lines() {
+        for line in &["", "  ", "a", "de", "dev", "dev:", ":", "cfg(", "é", "日本"] {
+            assert!(
+                matches!(
+                    line.parse::<Dependency>(),
+                    Err(CargoPlayError::InvalidDependency(_))
+                ),
+                "{:?}",
+                line
+            );
+        }
+
+        for line in &[r#"dév = "1""#, r#"dev: 日本 = "1""#] {
+            let dependency = line.parse::<Dependency>().unwrap();
+            assert_eq!(
+                dependency.spec,
+                line.trim_start_matches("dev: "),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_dependency() {
+        for line in &[
+            "serde",
+            r#"dev: serde "1""#,
+            "serde =",
+            "serde =  ",
+            r#"= "1""#,
+        ] {
+            assert!(
+                matches!(
+                    line.parse::<Dependency>(),
+                    Err(CargoPlayError::InvalidDependency(ref header)) if header.contains(line.trim())
+                ),
+                "{:?}",
+                line
+            );
+        }
+
+        let dev = r#"dev: serde = "1""#.parse::<Dependency>().unwrap();
+        assert_eq!(dev.kind, DependencyKind::Dev);
+        assert_eq!(dev.spec, r#"serde = "1""#);
+    }
+
+    #[test]
+    fn test_dependency_target_prefix() {
+        let cases = [
+            (r#"cfg(unix): nix = "0.27""#, DependencyKind::Normal),
+            (r#"dev: cfg(unix): nix = "0.27""#, DependencyKind::Dev),
+            (r#"cfg(unix) : dev: nix = "0.27""#, DependencyKind::Dev),
+            (r#"build: cfg(unix): nix = "0.27""#, DependencyKind::Build),
+        ];
+
+        for (line, kind) in &cases {
+            let dependency = line.parse::<Dependency>().unwrap();
+            assert_eq!(dependency.kind, *kind, "{}", line);
+            assert_eq!(dependency.target.as_deref(), Some("cfg(unix)"), "{}", line);
+            assert_eq!(dependency.spec, r#"nix = "0.27""#, "{}", line);
+        }
+
+        let nested = r#"dev: cfg(all(unix, target_os = "linux")): nix = "0.27""#
+            .parse::<Dependency>()
+            .unwrap();
+        assert_eq!(nested.kind, DependencyKind::Dev);
+        assert_eq!(
+            nested.target.as_deref(),
+            Some(r#"cfg(all(unix, target_os = "linux"))"#)
+        );
+        assert_eq!(nested.spec, r#"nix = "0.27""#);
+
+        let plain = r#"dev: nix = "0.27""#.parse::<Dependency>().unwrap();
+        assert_eq!(plain.target, None);
+    }
+
+    #[test]
+    fn test_parse_version_of() {
+        for args in &[
+            &["cargo-play", "--version-of", "serde"][..],
+            &["cargo", "play", "--version-of=serde"][..],
+        ] {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            assert!(matches!(
+                Opt::parse(args),
+                Ok(Invocation::VersionOf(name)) if name == "serde"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let parse = |args: &[&str]| Opt::parse(args.iter().map(|arg| arg.to_string()).collect());
+
+        assert!(matches!(
+            parse(&["cargo", "play", "--list-editions"]),
+            Ok(Invocation::ListEditions)
+        ));
+        assert!(matches!(
+            parse(&["cargo-play", "--list-toolchains"]),
+            Ok(Invocation::ListToolchains)
+        ));
+    }
+
+    #[test]
+    fn test_cfg() {
+        assert!(cfg_valid("mycfg".into()).is_ok());
+        assert!(cfg_valid("feature=foo".into()).is_ok());
+        assert!(cfg_valid("feature=\"foo\"".into()).is_ok());
+        assert!(cfg_valid("1cfg".into()).is_err());
+        assert!(cfg_valid("feature=".into()).is_err());
+        assert!(cfg_valid("feature=foo bar".into()).is_err());
+
+        let opt = Opt {
+            cfg: vec!["mycfg".into(), "feature=foo".into()],
+            ..Opt::default()
+        };
+        assert!(opt
+            .rustflags()
+            .unwrap()
+            .ends_with("--cfg mycfg --cfg feature=\"foo\""));
+        assert_ne!(opt.src_hash(), Opt::default().src_hash());
+    }
+
+    #[test]
+    fn test_cargo_option_valid() {
+        assert!(cargo_option_valid("--features foo --locked".into()).is_ok());
+        assert!(cargo_option_valid("--manifest-path Cargo.toml".into()).is_err());
+        assert!(cargo_option_valid("--locked --manifest-path=Cargo.toml".into()).is_err());
+        assert!(cargo_option_valid("--target-dir /tmp/target".into()).is_err());
+        // only exact flags are rejected
+        assert!(cargo_option_valid("--manifest-paths".into()).is_ok());
+
+        let opt = Opt {
+            cargo_option: Some("--release --bin=tool --locked".into()),
+            ..Opt::default()
+        };
+        assert_eq!(opt.cargo_option_warnings().len(), 2);
+    }
+
+    #[test]
+    fn test_package_name() {
+        for i in 0..64 {
+            let opt = Opt {
+                src: vec![PathBuf::from(format!("/snippet-{}.rs", i))],
+                ..Default::default()
+            };
+            let name = opt.package_name();
+
+            assert!(name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_'));
+            assert!(name.ends_with(&opt.src_hash().to_lowercase()));
+        }
+
+        assert_eq!(package_name("Abc-D"), "abc-d");
+        assert_eq!(package_name("-Abc"), "_-abc");
+        assert_eq!(package_name("9abc"), "_9abc");
+        assert_eq!(package_name(&package_name("9Abc")), "_9abc");
+
+        let opt = Opt {
+            name: Some("my-tool".into()),
+            src: vec![PathBuf::from("/snippet.rs")],
+            ..Default::default()
+        };
+        assert_eq!(opt.package_name(), "my-tool");
+
+        assert!(package_name_valid("my-tool".into()).is_ok());
+        assert!(package_name_valid("tool_2".into()).is_ok());
+        assert!(package_name_valid("My-Tool".into()).is_err());
+        assert!(package_name_valid("my tool".into()).is_err());
+        assert!(package_name_valid("2tool".into()).is_err());
+        assert!(package_name_valid("".into()).is_err());
+    }
+
+    #[test]
+    fn test_hash_options() {
+        let mut opt = Opt {
+            src: vec![PathBuf::from("/snippet.rs")],
+            ..Default::default()
+        };
+        let full = opt.src_hash();
+        assert_eq!(full.len(), 27);
+
+        opt.hash = HashAlgorithm::Sha256;
+        assert_eq!(opt.src_hash().len(), 43);
+        assert_ne!(&opt.src_hash()[..27], full);
+
+        opt.hash = HashAlgorithm::Sha1;
+        opt.hash_length = Some(8);
+        assert_eq!(opt.src_hash(), full[..8]);
+        assert_eq!(
+            opt.temp_dirname(),
+            PathBuf::from(format!("cargo-play.{}", &full[..8]))
+        );
+        assert!(opt.package_name().ends_with(&full[..8].to_lowercase()));
+
+        assert!(hash_length_valid("4".into()).is_ok());
+        assert!(hash_length_valid("3".into()).is_err());
+        assert!(hash_length_valid("short".into()).is_err());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let dir = std::env::temp_dir().join(format!("cargo-play-hash-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("a.rs"), dir.join("b.rs"));
+        std::fs::write(&first, "fn main() {}\n").unwrap();
+        std::fs::write(&second, "mod a;\n").unwrap();
+
+        let opt = Opt::with_files(vec![&second, &first]);
+        let original = opt.src_hash();
+        // the order the files are given in doesn't matter
+        assert_eq!(Opt::with_files(vec![&first, &second]).src_hash(), original);
+
+        std::fs::write(&first, "fn main() { println!(\"edited\"); }\n").unwrap();
+        assert_ne!(opt.src_hash(), original);
+
+        std::fs::write(&first, "//# serde = \"1\"\nfn main() {}\n").unwrap();
+        assert_ne!(opt.src_hash(), original);
+
+        std::fs::write(&first, "fn main() {}\n").unwrap();
+        assert_eq!(opt.src_hash(), original);
+
+        // projects updated in place follow the paths only
+        let mut opt = opt;
+        opt.check_only_changed = true;
+        let in_place = opt.src_hash();
+        std::fs::write(&first, "fn main() { println!(\"edited\"); }\n").unwrap();
+        assert_eq!(opt.src_hash(), in_place);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_version_style_requirement() {
+        assert_eq!(VersionStyle::Wildcard.requirement(Some("1.2.3")), "*");
+        assert_eq!(VersionStyle::Caret.requirement(Some("1.2.3")), "^1.2");
+        assert_eq!(VersionStyle::Exact.requirement(Some("1.2.3")), "=1.2.3");
+        assert_eq!(VersionStyle::Exact.requirement(None), "*");
+    }
+
+    #[test]
+    fn test_profile_setting() {
+        let setting: ProfileSetting = "dev.opt-level=1".parse().unwrap();
+        assert_eq!(setting.profile, "dev");
+        assert_eq!(setting.key, "opt-level");
+        assert_eq!(setting.value, toml::Value::Integer(1));
+
+        let setting: ProfileSetting = "release.lto = true".parse().unwrap();
+        assert_eq!(setting.value, toml::Value::Boolean(true));
+
+        let setting: ProfileSetting = "release.lto=fat".parse().unwrap();
+        assert_eq!(setting.value, toml::Value::String("fat".into()));
+
+        let setting: ProfileSetting = "dev.opt-level=\"s\"".parse().unwrap();
+        assert_eq!(setting.value, toml::Value::String("s".into()));
+
+        // unknown keys are left for cargo to check
+        let setting: ProfileSetting = "dev.something-new=yes".parse().unwrap();
+        assert_eq!(setting.value, toml::Value::String("yes".into()));
+
+        assert!("dev.opt-level=fast".parse::<ProfileSetting>().is_err());
+        assert!("release.codegen-units=true"
+            .parse::<ProfileSetting>()
+            .is_err());
+        assert!("opt-level=1".parse::<ProfileSetting>().is_err());
+        assert!("dev.debug".parse::<ProfileSetting>().is_err());
+    }
+
+    #[test]
+    fn test_env_var() {
+        let var: EnvVar = "QUERY=a=b".parse().unwrap();
+        assert_eq!(var.name, "QUERY");
+        assert_eq!(var.value, "a=b");
+
+        let var: EnvVar = "EMPTY=".parse().unwrap();
+        assert_eq!(var.value, "");
+
+        let error = "RUST_BACKTRACE".parse::<EnvVar>().unwrap_err();
+        assert!(error.to_string().contains("expected KEY=VALUE"));
+        assert!("=1".parse::<EnvVar>().is_err());
+        assert!("MY VAR=1".parse::<EnvVar>().is_err());
+    }
+
+    #[test]
+    fn test_expand_shebang_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let script = "fixtures/shebang.rs";
+
+        assert_eq!(
+            expand_shebang_args(args(&[
+                "cargo-play",
+                "--release --quiet-on-success",
+                script
+            ])),
+            args(&["cargo-play", "--release", "--quiet-on-success", script])
+        );
+
+        // the flags don't come from the script's shebang
+        assert_eq!(
+            expand_shebang_args(args(&["cargo-play", "--release --lib", script])),
+            args(&["cargo-play", "--release --lib", script])
+        );
+        assert_eq!(
+            expand_shebang_args(args(&["cargo-play", "--release", "fixtures/hello.rs"])),
+            args(&["cargo-play", "--release", "fixtures/hello.rs"])
+        );
+    }
+
+    #[test]
+    fn test_dependency_colon_in_spec() {
+        let dependency = r#"dtoa = { git = "https://github.com/dtolnay/dtoa.git" }"#
+            .parse::<Dependency>()
+            .unwrap();
+        assert_eq!(dependency.kind, DependencyKind::Normal);
+
+        let dependency = r#"dev: dtoa = { git = "https://github.com/dtolnay/dtoa.git" }"#
+            .parse::<Dependency>()
+            .unwrap();
+        assert_eq!(dependency.kind, DependencyKind::Dev);
+        assert_eq!(
+            dependency.spec,
+            r#"dtoa = { git = "https://github.com/dtolnay/dtoa.git" }"#
+        );
+    }
+}
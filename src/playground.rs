@@ -0,0 +1,244 @@
+//! The pipeline behind `cargo play`, usable without going through the command line.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+
+use log::warn;
+
+use crate::errors::CargoPlayError;
+use crate::infer;
+use crate::opt::{Allocator, CargoAction, Opt, OutputFormat};
+use crate::steps::*;
+
+/// The manifest generated for the sources, with what it was generated from.
+struct Generated {
+    manifest: Vec<u8>,
+    files: Vec<String>,
+    allocator: Option<Allocator>,
+}
+
+/// A snippet with the options it is played with.
+///
+/// The options are taken as given: the defaults `cargo play` derives from the environment, like
+/// `--quiet-on-success` on a terminal or `--lib` for test-only sources, are left to the caller.
+pub struct Playground {
+    opt: Opt,
+    /// Directory of the generated project
+    temp: PathBuf,
+    /// Removes the project of `--no-cache` when the playground is dropped
+    _scratch: Option<ScratchDir>,
+}
+
+impl Playground {
+    pub fn new(opt: Opt) -> Self {
+        let temp = if opt.no_cache {
+            scratch_dir(&opt)
+        } else {
+            temp_dir(opt.temp_dirname())
+        };
+        let _scratch = opt.no_cache.then(|| ScratchDir(temp.clone()));
+
+        Playground {
+            opt,
+            temp,
+            _scratch,
+        }
+    }
+
+    /// A playground generating its project in `dir`, e.g. a member of a `--workspace`.
+    pub fn in_dir(opt: Opt, dir: PathBuf) -> Self {
+        Playground {
+            opt,
+            temp: dir,
+            _scratch: None,
+        }
+    }
+
+    pub fn opt(&self) -> &Opt {
+        &self.opt
+    }
+
+    /// Directory of the project that is built: the generated one, or the existing Cargo project
+    /// given as the first source.
+    pub fn project(&self) -> &Path {
+        self.opt.existing_project().unwrap_or(&self.temp)
+    }
+
+    /// Generate the manifest of the project without writing anything.
+    pub fn manifest(&self) -> Result<Vec<u8>, CargoPlayError> {
+        self.generate().map(|generated| generated.manifest)
+    }
+
+    fn generate(&self) -> Result<Generated, CargoPlayError> {
+        let opt = &self.opt;
+        let files = parse_inputs(&opt.sources())?;
+        let (mut dependencies, package) =
+            split_package_headers(extract_headers(&opt.sources(), &files))?;
+
+        if let (true, Some(version)) = (opt.enforce_rust_version, &package.rust_version) {
+            check_rust_version(opt, version)?;
+        }
+
+        let allocator = opt.allocator.filter(|_| {
+            let defined = defines_global_allocator(&files[0]);
+            if defined {
+                warn!("the source defines a #[global_allocator], ignoring --allocator");
+            }
+            !defined
+        });
+        if let Some(dependency) = allocator.and_then(|allocator| allocator.dependency()) {
+            // headers of the source come later, so they win over the default version
+            dependencies.insert(0, dependency.into());
+        }
+
+        let infers = if opt.infer {
+            infer::analyze_sources(&opt.sources(), opt.infer_level)?
+        } else {
+            HashSet::new()
+        };
+
+        let manifest = generate_cargo_toml(opt, opt.package_name(), dependencies, package, infers)?;
+
+        if opt.pre_expand_macros {
+            check_macro_providers(&files, &manifest)?;
+        }
+
+        Ok(Generated {
+            manifest,
+            files,
+            allocator,
+        })
+    }
+
+    /// Write the project with its manifest and sources, returning its directory. When the first
+    /// source is the directory of an existing Cargo project, that directory is returned as is.
+    pub fn prepare(&self) -> Result<PathBuf, CargoPlayError> {
+        let opt = &self.opt;
+        let temp = &self.temp;
+
+        if let Some(project) = opt.existing_project() {
+            return Ok(project.to_path_buf());
+        }
+
+        let Generated {
+            manifest,
+            files,
+            allocator,
+        } = self.generate()?;
+
+        if opt.clean {
+            rmtemp(&temp_dir(opt.temp_dirname()));
+        }
+        mktemp(temp);
+        write_cargo_toml(temp, &manifest)?;
+        // the binary of a library is part of the project as well
+        let sources: Vec<_> = opt.src.iter().chain(&opt.main).cloned().collect();
+        write_source_index(temp, &sources)?;
+        write_build_script(temp, opt)?;
+        if let Some(record) = &opt.record_manifest {
+            record_manifest(temp, record.as_deref())?;
+        }
+        if let Some(project) = &opt.from_project {
+            copy_project_lock(temp, project)?;
+        }
+        if opt.concat {
+            write_concatenated(temp, &files, opt.lib)?;
+        } else {
+            copy_sources(temp, &opt.src, opt.lib, opt.check_only_changed)?;
+        }
+        copy_included_files(temp, &opt.src, opt.concat)?;
+        if let Some(main) = &opt.main {
+            copy_main(temp, main)?;
+            copy_included_files(temp, std::slice::from_ref(main), false)?;
+        }
+        if let Some(name) = &opt.main_fn {
+            append_main_fn(temp, name)?;
+        }
+        if opt.run_as_test {
+            wrap_as_test(temp)?;
+        }
+        if let Some(allocator) = allocator {
+            inject_allocator(temp, allocator)?;
+        }
+        if !opt.lib || opt.main.is_some() {
+            wire_main_result(temp)?;
+            wire_async_main(temp, &manifest)?;
+        }
+        copy_test_files(temp, &opt.test_files)?;
+
+        Ok(temp.clone())
+    }
+
+    /// Prepare the project and run the cargo action on it, returning the exit status of the
+    /// program, or of cargo when it does not run one.
+    ///
+    /// A failing pre-build with `--output-format json` or `--wrap-errors` is reported as
+    /// `BuildFailed`.
+    pub fn run(&self) -> Result<ExitStatus, CargoPlayError> {
+        let opt = &self.opt;
+        let package_name = opt.package_name();
+
+        ensure_rustup_installs(opt)?;
+        if opt.container.is_some() {
+            container_runtime()?;
+        }
+
+        if let Some(project) = opt.existing_project() {
+            return match prebuild(opt, project)? {
+                Some(build) if !build.success() => Ok(build),
+                _ => run_cargo_build(opt, project),
+            };
+        }
+
+        if opt.cached && opt.cargo_action() == CargoAction::Run && self.temp.exists() {
+            let bin_path = binary_path(opt, &self.temp, &package_name);
+            if is_fresh(&bin_path, &opt.src) {
+                return Ok(program_command(opt, &bin_path)
+                    .args(&opt.args)
+                    .envs(env_vars(opt)?)
+                    .stderr(Stdio::inherit())
+                    .stdout(Stdio::inherit())
+                    .status()?);
+            }
+        }
+
+        let temp = self.prepare()?;
+
+        let end = match prebuild(opt, &temp)? {
+            Some(build) if !build.success() => {
+                if opt.output_format == OutputFormat::Json || opt.wrap_errors {
+                    return Err(CargoPlayError::BuildFailed(build.code()));
+                }
+                build
+            }
+            _ if opt.debug_run => debug_run(opt, &temp, &package_name)?,
+            _ => match opt.run_count {
+                Some(count) => run_repeatedly(opt, &temp, &package_name, count)?,
+                None => run_cargo_build(opt, &temp)?,
+            },
+        };
+
+        if end.success() {
+            if let Some(out_dir) = &opt.out_dir {
+                export_artifacts(opt, &temp, &package_name, out_dir)?;
+            }
+            for file in emitted_files(opt, &temp, &package_name)? {
+                println!("{}", file.display());
+            }
+            if let Some(hook) = &opt.on_success {
+                run_on_success(opt, hook, &temp, &package_name)?;
+            }
+        }
+
+        if opt.print_deps {
+            print_deps(&temp, &package_name)?;
+        }
+
+        if opt.trace_cargo {
+            print_feature_tree(opt, &temp)?;
+        }
+
+        Ok(end)
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io;
+use std::process::Stdio;
+use std::thread::JoinHandle;
+
+/// A pseudo-terminal pair. The program writes to the slave side, and its output is forwarded from
+/// the master side to our stdout, so the program sees a terminal even when cargo-play is piped.
+pub struct Pty {
+    master: File,
+    slave: File,
+}
+
+impl Pty {
+    #[cfg(unix)]
+    pub fn open() -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let mut master = 0;
+        let mut slave = 0;
+        let size = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+
+        // Safety: all pointers are valid for the duration of the call
+        let ret = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                &size,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Safety: `openpty` succeeded, so both are open file descriptors we now own
+        let pty = unsafe {
+            Pty {
+                master: File::from_raw_fd(master),
+                slave: File::from_raw_fd(slave),
+            }
+        };
+        pty.disable_output_processing()?;
+
+        Ok(pty)
+    }
+
+    #[cfg(not(unix))]
+    pub fn open() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "pseudo-terminals are only supported on Unix",
+        ))
+    }
+
+    /// Keep `\n` as is instead of translating it to `\r\n`, since the output usually ends up in
+    /// a pipe or a file rather than a real terminal.
+    #[cfg(unix)]
+    fn disable_output_processing(&self) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = self.slave.as_raw_fd();
+        // Safety: `termios` is plain data and is fully initialized by `tcgetattr`
+        unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.c_oflag &= !libc::OPOST;
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn slave_stdio(&self) -> io::Result<Stdio> {
+        Ok(Stdio::from(self.slave.try_clone()?))
+    }
+
+    /// Copy everything written to the terminal to our stdout until the program exits. This must
+    /// be called after the program is spawned so the slave side is closed once the program is done.
+    pub fn forward_output(self) -> JoinHandle<()> {
+        let Pty { mut master, slave } = self;
+        drop(slave);
+
+        std::thread::spawn(move || {
+            // Reading the master side fails with EIO once every slave is closed, which marks the
+            // end of the output just like EOF does.
+            let _ = io::copy(&mut master, &mut io::stdout());
+        })
+    }
+}
@@ -1,49 +1,221 @@
 use std::collections::HashSet;
 use std::env;
+use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{Read, Write};
-use std::iter::Iterator;
+use std::io::{IsTerminal, Read, Write};
+use std::iter::{self, Iterator};
 use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
+use std::sync::OnceLock;
 use std::vec::Vec;
 
-use log::debug;
+use log::{debug, info, warn};
 use pathdiff::diff_paths;
 
-use crate::cargo::CargoManifest;
+use crate::cargo::{
+    read_frontmatter, read_manifest_include, read_project_dependencies, read_project_edition,
+    read_sidecar, resolve_sources, CargoLock, CargoManifest, DependencySource,
+};
 use crate::errors::CargoPlayError;
-use crate::opt::RustEdition;
+use crate::explain;
+use crate::includes;
+use crate::logger;
+use crate::macros;
+use crate::opt::{
+    package_name, Allocator, CargoAction, Dependency, DependencyKind, NewOpt, Opt, OutputFormat,
+    RustEdition, VersionStyle,
+};
+use crate::pty::Pty;
+use crate::templates;
+use toml::value::{Table, Value};
 
 pub fn parse_inputs(inputs: &[PathBuf]) -> Result<Vec<String>, CargoPlayError> {
     inputs
         .iter()
-        .map(File::open)
-        .map(|res| match res {
-            Ok(mut fp) => {
-                let mut buf = String::new();
-                fp.read_to_string(&mut buf)?;
-                Ok(buf)
-            }
-            Err(e) => Err(CargoPlayError::from(e)),
+        .map(|input| {
+            let mut buf = String::new();
+            File::open(input)?
+                .read_to_string(&mut buf)
+                .map_err(|e| match e.kind() {
+                    std::io::ErrorKind::InvalidData => CargoPlayError::NonUtf8Source(input.clone()),
+                    _ => CargoPlayError::from(e),
+                })?;
+            Ok(buf)
         })
         .collect()
 }
 
-pub fn extract_headers(files: &[String]) -> Vec<String> {
-    files
+fn is_header_preamble(line: &str) -> bool {
+    line.starts_with("#!") || line.is_empty()
+}
+
+/// A `//#` header line, with the source file and line number it was found at.
+#[derive(Debug, PartialEq)]
+pub struct Header {
+    pub text: String,
+    pub path: PathBuf,
+    pub line: usize,
+    /// Whether the line is part of a TOML frontmatter, between `//# ---` fences.
+    pub frontmatter: bool,
+}
+
+/// The fence around a TOML frontmatter among the headers.
+const FRONTMATTER_FENCE: &str = "---";
+
+/// The `//#` headers at the top of each of the `files`, read from the matching `paths`.
+pub fn extract_headers(paths: &[PathBuf], files: &[String]) -> Vec<Header> {
+    paths
         .iter()
-        .map(|file: &String| -> Vec<String> {
+        .zip(files)
+        .flat_map(|(path, file)| -> Vec<Header> {
+            let mut frontmatter = false;
+
             file.lines()
-                .skip_while(|line| line.starts_with("#!") || line.is_empty())
-                .take_while(|line| line.starts_with("//#"))
-                .map(|line| line[3..].trim_start().into())
-                .filter(|s: &String| !s.is_empty())
+                .enumerate()
+                .skip_while(|(_, line)| is_header_preamble(line))
+                .take_while(|(_, line)| line.starts_with("//#"))
+                .filter_map(|(index, line)| {
+                    let text = line[3..].trim();
+                    if text == FRONTMATTER_FENCE {
+                        frontmatter = !frontmatter;
+                        return None;
+                    }
+
+                    Some(Header {
+                        text: text.into(),
+                        path: path.clone(),
+                        line: index + 1,
+                        frontmatter,
+                    })
+                })
+                .filter(|header| !header.text.is_empty())
                 .collect()
         })
-        .flatten()
         .collect()
 }
 
+/// Remove the shebang and the `//#` headers at the top of a source file.
+fn strip_headers(file: &str) -> String {
+    let mut in_headers = true;
+
+    file.lines()
+        .skip_while(|line| is_header_preamble(line))
+        .filter(|line| {
+            in_headers = in_headers && line.starts_with("//#");
+            !in_headers
+        })
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Join the sources, in order and without their headers, into a single crate root.
+pub fn concat_sources(files: &[String]) -> String {
+    files
+        .iter()
+        .map(|file| strip_headers(file))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Headers describing the generated package itself rather than its dependencies.
+#[derive(Debug, Default)]
+pub struct PackageHeaders {
+    /// From `//# edition = "2018"` or `//# edition: 2018`. The last one wins.
+    pub edition: Option<RustEdition>,
+    /// `[[bin]]` targets from `//# bin: name = "tool", path = "src/main.rs"`.
+    pub bins: Vec<Table>,
+    /// From `//# rust-version: 1.70`, the minimum supported Rust version.
+    pub rust_version: Option<String>,
+    /// The TOML fragment between `//# ---` fences, see `read_frontmatter`.
+    pub frontmatter: String,
+}
+
+/// Match a package header in either the `edition = "2018"` or the `edition: 2018` form.
+fn package_header<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    let (key, value) = header.split_once('=').or_else(|| header.split_once(':'))?;
+
+    if key.trim() == name {
+        Some(value.trim().trim_matches('"'))
+    } else {
+        None
+    }
+}
+
+/// Parse a `bin: name = "tool", path = "src/main.rs"` header into a `[[bin]]` table. The path
+/// defaults to `src/main.rs`.
+fn bin_header(header: &str) -> Option<Result<Table, CargoPlayError>> {
+    let (key, value) = header.split_once(':')?;
+
+    if !key.trim().eq_ignore_ascii_case("bin") {
+        return None;
+    }
+
+    let bin = format!("bin = {{ {} }}", value.trim())
+        .parse::<Value>()
+        .map_err(CargoPlayError::from_serde)
+        .and_then(
+            |mut table| match table.as_table_mut().and_then(|t| t.remove("bin")) {
+                Some(Value::Table(mut bin)) if bin.contains_key("name") => {
+                    bin.entry("path".to_string())
+                        .or_insert_with(|| Value::String("src/main.rs".into()));
+                    Ok(bin)
+                }
+                _ => Err(CargoPlayError::ParseError(format!(
+                    "binary target without a name: {}",
+                    header
+                ))),
+            },
+        );
+
+    Some(bin)
+}
+
+/// Separate headers describing the package from dependency headers.
+pub fn split_package_headers(
+    headers: Vec<Header>,
+) -> Result<(Vec<String>, PackageHeaders), CargoPlayError> {
+    let mut package = PackageHeaders::default();
+    let mut dependencies = Vec::new();
+
+    for Header {
+        text: header,
+        path,
+        line,
+        frontmatter,
+    } in headers
+    {
+        if frontmatter {
+            package.frontmatter.push_str(&header);
+            package.frontmatter.push('\n');
+        } else if let Some(value) = package_header(&header, "edition") {
+            package.edition = Some(value.parse()?);
+        } else if let Some(value) = package_header(&header, "rust-version") {
+            if parse_rust_version(value).is_none() {
+                return Err(CargoPlayError::ParseError(format!(
+                    "invalid rust-version {:?}, expected e.g. 1.70",
+                    value
+                )));
+            }
+            package.rust_version = Some(value.into());
+        } else if let Some(bin) = bin_header(&header) {
+            package.bins.push(bin?);
+        } else {
+            // checked here, where the header can still be pointed at
+            if let Err(CargoPlayError::InvalidDependency(text)) = header.parse::<Dependency>() {
+                return Err(CargoPlayError::InvalidDependency(format!(
+                    "{} at {}:{}",
+                    text,
+                    path.display(),
+                    line
+                )));
+            }
+            dependencies.push(header);
+        }
+    }
+
+    Ok((dependencies, package))
+}
+
 pub fn temp_dir(name: PathBuf) -> PathBuf {
     let mut temp = PathBuf::new();
     temp.push(env::temp_dir());
@@ -51,12 +223,61 @@ pub fn temp_dir(name: PathBuf) -> PathBuf {
     temp
 }
 
+/// Save the source read from stdin as a file going through the same steps as the others. The
+/// file of an earlier run with the same input is kept as it is, so `--cached` finds it unchanged.
+pub fn save_stdin_source(opt: &Opt) -> Result<PathBuf, CargoPlayError> {
+    let mut source = Vec::new();
+    std::io::stdin().read_to_end(&mut source)?;
+
+    let dir = temp_dir(opt.stdin_dirname(&source));
+    let path = dir.join("stdin.rs");
+    if !path.is_file() {
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(&path, &source)?;
+    }
+
+    Ok(path)
+}
+
 /// This function ignores the error intentionally.
 pub fn rmtemp(temp: &PathBuf) {
     debug!("Cleaning temporary folder at: {:?}", temp);
     let _ = std::fs::remove_dir_all(temp);
 }
 
+/// A temporary project removed when dropped, see `--no-cache`.
+pub struct ScratchDir(pub PathBuf);
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        rmtemp(&self.0);
+    }
+}
+
+/// Memory-backed filesystem the `--scratch` projects are placed on, if the system has one.
+fn memory_fs() -> Option<PathBuf> {
+    let shm = Path::new("/dev/shm");
+
+    if cfg!(target_os = "linux") && shm.is_dir() {
+        Some(shm.into())
+    } else {
+        None
+    }
+}
+
+/// Directory of the one-off project of `--no-cache`, or of `--scratch` which prefers a
+/// memory-backed filesystem over the regular temporary directory.
+pub fn scratch_dir(opt: &Opt) -> PathBuf {
+    if opt.scratch {
+        match memory_fs() {
+            Some(memory) => return memory.join(opt.scratch_dirname()),
+            None => warn!("no memory-backed filesystem found, using the temporary directory"),
+        }
+    }
+
+    temp_dir(opt.scratch_dirname())
+}
+
 pub fn mktemp(temp: &PathBuf) {
     debug!("Creating temporary building folder at: {:?}", temp);
     if std::fs::create_dir(temp).is_err() {
@@ -64,120 +285,2901 @@ pub fn mktemp(temp: &PathBuf) {
     }
 }
 
-pub fn write_cargo_toml(
-    dir: &PathBuf,
+/// The name and version of `name` in the output of `cargo search`, whose lines read
+/// `name = "1.2.3"    # description`. Names are compared the way crates.io does, `-` and `_`
+/// being the same, and returned as the registry spells them.
+fn search_result(output: &str, name: &str) -> Option<(String, String)> {
+    let normalize = |name: &str| name.replace('-', "_");
+    let wanted = normalize(name);
+
+    output.lines().find_map(|line| {
+        let (found, rest) = line.split_once(" = \"")?;
+        if normalize(found) != wanted {
+            return None;
+        }
+        let version = rest.split('"').next()?;
+        Some((found.to_string(), version.to_string()))
+    })
+}
+
+/// The `cargo search` invocation looking `name` up, in the registry `index` if given.
+fn search_command(toolchain: Option<&str>, index: Option<&str>, name: &str) -> Command {
+    let mut cargo = Command::new("cargo");
+    if let Some(toolchain) = toolchain {
+        cargo.arg(format!("+{}", toolchain));
+    }
+
+    cargo.args(["search", "--limit", "1"]);
+    if let Some(index) = index {
+        cargo.arg("--index").arg(index);
+    }
+    cargo.arg(name);
+
+    cargo
+}
+
+/// Look up the latest version of a crate on the registry with `cargo search`, returning the name
+/// of the crate as the registry spells it along with the version. An unreachable registry finds
+/// nothing.
+fn latest_version(
+    toolchain: Option<&str>,
+    index: Option<&str>,
+    name: &str,
+) -> Option<(String, String)> {
+    let output = search_command(toolchain, index, name)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    search_result(&String::from_utf8_lossy(&output.stdout), name)
+}
+
+/// The sidecar declaring the dependencies of `source`: `<name>.deps` next to it, or else
+/// `Cargo.deps.toml` in its directory.
+pub fn sidecar_path(source: &Path) -> Option<PathBuf> {
+    let candidates = vec![
+        Some(source.with_extension("deps")),
+        source.parent().map(|dir| dir.join("Cargo.deps.toml")),
+    ];
+
+    candidates.into_iter().flatten().find(|path| path.is_file())
+}
+
+/// Serialize the manifest of the generated project into `Cargo.toml` bytes.
+pub fn generate_cargo_toml(
+    opt: &Opt,
     name: String,
     dependencies: Vec<String>,
-    edition: RustEdition,
-    infers: HashSet<String>,
-) -> Result<(), CargoPlayError> {
-    let mut manifest = CargoManifest::new(name, dependencies, edition)?;
-    let mut cargo = File::create(dir.join("Cargo.toml"))?;
+    package: PackageHeaders,
+    mut infers: HashSet<String>,
+) -> Result<Vec<u8>, CargoPlayError> {
+    let edition = opt.edition.or(package.edition).unwrap_or_default();
+    let mut manifest = CargoManifest::new(name.clone(), dependencies, edition)?;
+
+    let (sections, profiles) = read_frontmatter(&package.frontmatter)?;
+    for (kind, dependencies) in sections {
+        for (name, source) in
+            manifest.merge_dependencies(DependencySource::Frontmatter, kind, dependencies)
+        {
+            warn!(
+                "dependency `{}` of the frontmatter is overridden by the {}",
+                name, source
+            );
+        }
+    }
+
+    if let Some(sidecar) = sidecar_path(&opt.src[0]) {
+        for (kind, dependencies) in read_sidecar(&std::fs::read_to_string(&sidecar)?)? {
+            for (name, source) in
+                manifest.merge_dependencies(DependencySource::Sidecar, kind, dependencies)
+            {
+                warn!(
+                    "dependency `{}` of {:?} is overridden by the {}",
+                    name, sidecar, source
+                );
+            }
+        }
+    }
+
+    // headers, the frontmatter and the sidecar all sit next to the first source
+    if let Some(dir) = opt.src[0].parent() {
+        manifest.resolve_sources(dir)?;
+    }
+
+    if let Some(include) = &opt.manifest_include {
+        for (kind, mut dependencies) in read_manifest_include(&std::fs::read_to_string(include)?)? {
+            if let Some(dir) = include.parent() {
+                resolve_sources(&mut dependencies, dir)?;
+            }
+            for (name, source) in
+                manifest.merge_dependencies(DependencySource::ManifestInclude, kind, dependencies)
+            {
+                warn!(
+                    "dependency `{}` of {:?} is overridden by the {}",
+                    name, include, source
+                );
+            }
+        }
+    }
+
+    if let Some(project) = &opt.from_project {
+        let dependencies = read_project_dependencies(project)?;
+        for (name, source) in manifest.merge_dependencies(
+            DependencySource::Project,
+            DependencyKind::Normal,
+            dependencies,
+        ) {
+            warn!(
+                "dependency `{}` of {:?} is overridden by the {}",
+                name, project, source
+            );
+        }
+    }
+
+    if let Some(lib) = opt.lib_name() {
+        // the `--main` binary uses the library by its crate name
+        infers.remove(&lib);
+        manifest.set_lib(lib);
+    }
+
+    let style = opt.infer_version_style;
+    manifest.add_infers(infers, |name| match style {
+        VersionStyle::Wildcard => (name.into(), style.requirement(None)),
+        // `cargo search` needs the network
+        _ if opt.offline || opt.frozen => (name.into(), style.requirement(None)),
+        _ => {
+            // lookups block on the network one crate at a time, so each gets a status line
+            if !opt.quiet {
+                logger::status("Searching", format_args!("{} on the registry", name));
+            }
+            match latest_version(opt.toolchain.as_deref(), opt.index.as_deref(), name) {
+                Some((name, version)) => (name, style.requirement(Some(&version))),
+                None => {
+                    warn!(
+                        "unable to look up the latest version of `{}`, using \"*\"",
+                        name
+                    );
+                    (name.into(), style.requirement(None))
+                }
+            }
+        }
+    });
+
+    if let Some(max) = opt.max_deps {
+        manifest.check_dependency_count(max)?;
+    }
+
+    if opt.explain_deps {
+        for origin in manifest.origins() {
+            let overridden: Vec<String> = origin.overridden.iter().map(|s| s.to_string()).collect();
+            if overridden.is_empty() {
+                info!("{} ({:?}): {}", origin.name, origin.kind, origin.source);
+            } else {
+                info!(
+                    "{} ({:?}): {}, overriding {}",
+                    origin.name,
+                    origin.kind,
+                    origin.source,
+                    overridden.join(", ")
+                );
+            }
+        }
+    }
+
+    if opt.main.is_some() {
+        let mut bin = Table::new();
+        bin.insert("name".into(), Value::String(package_name(&name)));
+        bin.insert("path".into(), Value::String("src/main.rs".into()));
+        manifest.add_bins(vec![bin]);
+    }
+    if opt.cargo_action() == CargoAction::Bench
+        && !opt.lib
+        && package.bins.is_empty()
+        && !parse_inputs(&opt.src)?
+            .iter()
+            .any(|source| has_benches(source))
+    {
+        // `cargo bench` only runs `fn main` of a binary without the libtest harness
+        let mut bin = Table::new();
+        bin.insert("name".into(), Value::String(package_name(&name)));
+        bin.insert("path".into(), Value::String("src/main.rs".into()));
+        bin.insert("harness".into(), Value::Boolean(false));
+        manifest.add_bins(vec![bin]);
+    }
+    manifest.add_bins(package.bins);
+    if let Some(version) = &package.rust_version {
+        manifest.set_rust_version(version);
+    }
+    if let Some(resolver) = &opt.resolver {
+        manifest.set_resolver(resolver);
+    }
+    manifest.set_profiles(&profiles);
+    if let Some(strategy) = opt.panic {
+        if matches!(opt.cargo_action(), CargoAction::Test | CargoAction::Bench) {
+            warn!("--panic only applies to binaries, tests and benchmarks always unwind");
+        }
+        manifest.set_panic(strategy);
+    }
+    manifest.set_profiles(&opt.fast_profile());
+    manifest.set_profiles(&opt.profile_set);
+
+    manifest.serialize(opt.manifest_format)
+}
 
-    manifest.add_infers(infers);
+pub fn write_cargo_toml(dir: &Path, manifest: &[u8]) -> Result<(), CargoPlayError> {
+    emit_manifest(&dir.join("Cargo.toml"), manifest)
+}
+
+/// File in each cached project recording the sources it was generated from.
+const SOURCE_INDEX: &str = ".cargo-play-sources";
 
-    cargo.write_all(&toml::to_vec(&manifest).map_err(CargoPlayError::from_serde)?)?;
+/// Record the sources of the temporary project so `list-cache` can tell what it belongs to. The
+/// index is rewritten on every run, so its modification time is the time of the last run.
+pub fn write_source_index(temp: &Path, sources: &[PathBuf]) -> Result<(), CargoPlayError> {
+    let index: String = sources
+        .iter()
+        .map(|source| format!("{}\n", source.display()))
+        .collect();
+    std::fs::write(temp.join(SOURCE_INDEX), index)?;
 
     Ok(())
 }
 
-/// Copy all the passed in sources to the temporary directory. The first in the list will be
-/// treated as main.rs.
-pub fn copy_sources(temp: &PathBuf, sources: &[PathBuf]) -> Result<(), CargoPlayError> {
-    let destination = temp.join("src");
-    std::fs::create_dir_all(&destination)?;
+/// Write the `build.rs` of `--embed-version`, or remove the one of a previous run.
+pub fn write_build_script(temp: &Path, opt: &Opt) -> Result<(), CargoPlayError> {
+    let build_script = temp.join("build.rs");
 
-    let mut files = sources.iter();
-    let base = if let Some(first) = files.next() {
-        let dst = destination.join("main.rs");
-        debug!("Copying {:?} => {:?}", first, dst);
-        std::fs::copy(first, dst)?;
-        first.parent()
-    } else {
-        None
-    };
+    if !opt.embed_version {
+        if build_script.exists() {
+            std::fs::remove_file(build_script)?;
+        }
+        return Ok(());
+    }
 
-    if let Some(base) = base {
-        files
-            .map(|file| -> Result<(), CargoPlayError> {
-                let part = diff_paths(file, base)
-                    .ok_or_else(|| CargoPlayError::DiffPathError(file.to_owned()))?;
-                let dst = destination.join(part);
+    let script = format!(
+        r#"fn main() {{
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-env=CARGO_PLAY_SRC_HASH={}");
+    println!("cargo:rustc-env=CARGO_PLAY_VERSION={}");
+}}
+"#,
+        opt.src_hash(),
+        env!("CARGO_PKG_VERSION")
+    );
+    std::fs::write(build_script, script)?;
 
-                // ensure the parent folder all exists
-                if let Some(parent) = dst.parent() {
-                    let _ = std::fs::create_dir_all(&parent);
-                }
+    Ok(())
+}
 
-                debug!("Copying {:?} => {:?}", file, dst);
-                std::fs::copy(file, dst).map(|_| ()).map_err(From::from)
+/// Total size of the files under `path`.
+fn disk_usage(path: &Path) -> u64 {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| disk_usage(&entry.path()))
+                    .sum()
             })
-            .collect::<Result<Vec<_>, _>>()?;
+            .unwrap_or(0),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+fn human_age(age: std::time::Duration) -> String {
+    let secs = age.as_secs();
+
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
     }
+}
+
+/// Create a new snippet from a template, see `cargo play new`.
+pub fn new_snippet(new: &NewOpt) -> Result<(), CargoPlayError> {
+    if new.path.exists() {
+        return Err(CargoPlayError::PathExistError(new.path.clone()));
+    }
+
+    // the template name is validated when parsing the arguments
+    let content = templates::template(&new.template).unwrap_or_default();
+    std::fs::write(&new.path, content)?;
+    println!(
+        "Created {} from the {} template",
+        new.path.display(),
+        new.template
+    );
 
     Ok(())
 }
 
-pub fn run_cargo_build(
-    toolchain: Option<String>,
-    project: &PathBuf,
-    release: bool,
-    cargo_option: Option<String>,
-    program_args: &[String],
-) -> Result<ExitStatus, CargoPlayError> {
-    let mut cargo = Command::new("cargo");
+/// Print the latest version of a crate on the registry, see `cargo play --version-of`.
+pub fn version_of(name: &str) -> Result<(), CargoPlayError> {
+    let (name, version) = latest_version(None, None, name)
+        .ok_or_else(|| CargoPlayError::CrateNotFound(name.into()))?;
+    println!("{} = \"{}\"", name, version);
 
-    if let Some(toolchain) = toolchain {
-        cargo.arg(format!("+{}", toolchain));
+    Ok(())
+}
+
+/// Parse a `1.70` or `1.70.1` version, ignoring pre-release suffixes such as `-nightly`.
+pub fn parse_rust_version(version: &str) -> Option<(u32, u32, u32)> {
+    let version = version.split('-').next()?;
+    let mut parts = version.split('.').map(str::parse::<u32>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next()?.ok()?;
+    let patch = match parts.next() {
+        Some(patch) => patch.ok()?,
+        None => 0,
+    };
+
+    if parts.next().is_some() {
+        return None;
     }
+    Some((major, minor, patch))
+}
 
-    cargo
-        .arg("run")
-        .arg("--manifest-path")
-        .arg(project.join("Cargo.toml"));
+/// The version of `rustc --version` output, e.g. `rustc 1.75.0 (82e1608df 2023-12-21)`.
+fn rustc_full_version(output: &str) -> Option<(u32, u32, u32)> {
+    parse_rust_version(output.split_whitespace().nth(1)?)
+}
 
-    if let Some(cargo_option) = cargo_option {
-        // FIXME: proper escaping
-        cargo.args(cargo_option.split_ascii_whitespace());
+/// The major and minor version of `rustc --version` output.
+fn rustc_version(output: &str) -> Option<(u32, u32)> {
+    rustc_full_version(output).map(|(major, minor, _)| (major, minor))
+}
+
+/// Check that the rustc of the selected toolchain is at least the `rust-version` of the source,
+/// see `--enforce-rust-version`. Cargo only enforces it for dependencies.
+pub fn check_rust_version(opt: &Opt, required: &str) -> Result<(), CargoPlayError> {
+    let mut rustc = Command::new("rustc");
+    if let Some(toolchain) = &opt.toolchain {
+        rustc.arg(format!("+{}", toolchain));
     }
+    let output = rustc.arg("--version").output()?;
+    let output = String::from_utf8_lossy(&output.stdout);
 
-    if release {
-        cargo.arg("--release");
+    match (rustc_full_version(&output), parse_rust_version(required)) {
+        (Some(active), Some(needed)) if active < needed => {
+            let active = output.split_whitespace().nth(1).unwrap_or_default();
+            Err(CargoPlayError::RustVersionTooOld(
+                required.into(),
+                active.into(),
+            ))
+        }
+        (Some(_), Some(_)) => Ok(()),
+        _ => {
+            warn!("unable to determine the rustc version, not enforcing rust-version");
+            Ok(())
+        }
+    }
+}
+
+/// Print the editions `--edition` accepts, noting the ones the active toolchain is too old for,
+/// see `cargo play --list-editions`.
+pub fn list_editions() -> Result<(), CargoPlayError> {
+    let output = Command::new("rustc").arg("--version").output()?;
+    let active = rustc_version(&String::from_utf8_lossy(&output.stdout));
+
+    for edition in RustEdition::ALL {
+        let (major, minor) = edition.min_rustc();
+        let mut line = String::from(*edition);
+        if *edition == RustEdition::default() {
+            line.push_str(" (default)");
+        }
+        if active.is_some_and(|active| active < (major, minor)) {
+            line.push_str(&format!(" (needs rustc {}.{})", major, minor));
+        }
+        println!("{}", line);
     }
 
-    cargo
-        .arg("--")
-        .args(program_args)
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::inherit())
+    Ok(())
+}
+
+/// Print the toolchains installed with rustup, see `cargo play --list-toolchains`.
+pub fn list_toolchains() -> Result<(), CargoPlayError> {
+    let status = Command::new("rustup")
+        .args(["toolchain", "list"])
         .status()
-        .map_err(From::from)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => CargoPlayError::NoRustup("list toolchains".into()),
+            _ => e.into(),
+        })?;
+
+    if !status.success() {
+        return Err(CargoPlayError::CommandFailed(
+            "rustup toolchain list".into(),
+            status,
+        ));
+    }
+
+    Ok(())
 }
 
-pub fn copy_project<T: AsRef<Path>, U: AsRef<Path>>(
-    from: T,
-    to: U,
-) -> Result<ExitStatus, CargoPlayError> {
-    let to = to.as_ref();
+/// Print the hash, size, last run and sources of every cached project in the temp directory.
+pub fn list_cache() -> Result<(), CargoPlayError> {
+    let mut projects: Vec<PathBuf> = std::fs::read_dir(env::temp_dir())?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_dir()
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("cargo-play."))
+        })
+        .collect();
+    projects.sort();
 
-    if to.is_dir() {
-        return Err(CargoPlayError::PathExistError(to.to_path_buf()));
+    for project in projects {
+        let name = project.file_name().unwrap_or_default().to_string_lossy();
+        let hash = name.trim_start_matches("cargo-play.");
+        let index = project.join(SOURCE_INDEX);
+        let last_run = std::fs::metadata(&index)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .map_or_else(|| String::from("unknown"), human_age);
+        let sources = std::fs::read_to_string(&index).unwrap_or_default();
+
+        println!(
+            "{}\t{}\t{}",
+            hash,
+            human_size(disk_usage(&project)),
+            last_run
+        );
+        for source in sources.lines() {
+            println!("    {}", source);
+        }
     }
 
-    Command::new("cp")
-        .arg("-R")
-        .arg(from.as_ref())
-        .arg(&to)
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .status()
-        .map(|x| {
-            // At this point we are certain the `to` path exists
-            println!(
-                "Generated project at {}",
-                to.canonicalize().unwrap().display()
-            );
-            x
+    Ok(())
+}
+
+/// Default file `--record-manifest` writes to, in the current directory.
+const MANIFEST_RECORD: &str = ".cargo-play-last";
+
+/// Record the path of the generated manifest where editor plugins can discover it.
+pub fn record_manifest(temp: &Path, record: Option<&Path>) -> Result<(), CargoPlayError> {
+    let record = record.unwrap_or_else(|| Path::new(MANIFEST_RECORD));
+    let manifest = temp.join("Cargo.toml");
+
+    debug!("Recording manifest path {:?} in {:?}", manifest, record);
+    std::fs::write(
+        record,
+        format!("CARGO_PLAY_MANIFEST={}\n", manifest.display()),
+    )?;
+
+    Ok(())
+}
+
+/// Write the generated manifest to an arbitrary path, without setting up the rest of the project.
+pub fn emit_manifest(path: &Path, manifest: &[u8]) -> Result<(), CargoPlayError> {
+    let mut cargo = File::create(path)?;
+    cargo.write_all(manifest)?;
+
+    Ok(())
+}
+
+/// Clipboard tools tried in order, with the arguments making them read from stdin.
+#[cfg(target_os = "macos")]
+const CLIPBOARD_TOOLS: &[&[&str]] = &[&["pbcopy"]];
+#[cfg(windows)]
+const CLIPBOARD_TOOLS: &[&[&str]] = &[&["clip"]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const CLIPBOARD_TOOLS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+/// Copy the manifest to the clipboard with the first available tool, printing it to stdout
+/// instead when there is none.
+pub fn copy_to_clipboard(manifest: &[u8]) -> Result<(), CargoPlayError> {
+    for tool in CLIPBOARD_TOOLS {
+        let mut child = match Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(manifest)?;
+        }
+
+        if child.wait()?.success() {
+            debug!("Copied the manifest with {}", tool[0]);
+            return Ok(());
+        }
+    }
+
+    warn!("no clipboard tool is available, printing the manifest instead");
+    std::io::stdout().write_all(manifest)?;
+
+    Ok(())
+}
+
+/// State of `copy_sources`: the content hash and destination of every source copied into the
+/// project, one `hash path` line each.
+const COPIED_SOURCES: &str = ".cargo-play-copied";
+
+fn content_hash(content: &[u8]) -> String {
+    let mut hash = sha1::Sha1::new();
+    hash.update(content);
+    base64::encode_config(&hash.digest().bytes()[..], base64::URL_SAFE_NO_PAD)
+}
+
+/// Copy all the passed in sources to the temporary directory. The first in the list will be
+/// treated as main.rs, or lib.rs when building a library.
+///
+/// With `only_changed`, copies whose content is already up to date are left alone, so their
+/// modification time doesn't make cargo rebuild them, and copies of sources that are no longer
+/// given are removed.
+pub fn copy_sources(
+    temp: &Path,
+    sources: &[PathBuf],
+    lib: bool,
+    only_changed: bool,
+) -> Result<(), CargoPlayError> {
+    let destination = temp.join("src");
+    std::fs::create_dir_all(&destination)?;
+
+    let (root, stale) = if lib {
+        ("lib.rs", "main.rs")
+    } else {
+        ("main.rs", "lib.rs")
+    };
+    // the same sources may have been built as the other kind of crate before
+    let _ = std::fs::remove_file(destination.join(stale));
+
+    let base = match sources.first().and_then(|first| first.parent()) {
+        Some(base) => base,
+        None => return Ok(()),
+    };
+    let mut copies = vec![(&sources[0], destination.join(root))];
+
+    for file in &sources[1..] {
+        let part =
+            diff_paths(file, base).ok_or_else(|| CargoPlayError::DiffPathError(file.to_owned()))?;
+        // `..` or absolute paths would place the file outside of src/
+        if !part
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            return Err(CargoPlayError::SourceOutsideProject(file.to_owned()));
+        }
+        copies.push((file, destination.join(part)));
+    }
+
+    let state = temp.join(COPIED_SOURCES);
+    let previous = std::fs::read_to_string(&state).unwrap_or_default();
+    let mut copied = String::new();
+
+    for (file, dst) in &copies {
+        let content = std::fs::read(file)?;
+        let hash = content_hash(&content);
+        copied.push_str(&format!("{} {}\n", hash, dst.display()));
+
+        // the copy may have been modified after the fact, e.g. by --main-fn
+        let unchanged = || std::fs::read(dst).is_ok_and(|copy| content_hash(&copy) == hash);
+        if only_changed && unchanged() {
+            debug!("Skipping unchanged {:?}", file);
+            continue;
+        }
+
+        // ensure the parent folder all exists
+        if let Some(parent) = dst.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        debug!("Copying {:?} => {:?}", file, dst);
+        std::fs::write(dst, content)?;
+    }
+
+    if only_changed {
+        let removed = previous
+            .lines()
+            .filter_map(|line| line.split_once(' ').map(|(_, path)| PathBuf::from(path)))
+            .filter(|path| path.starts_with(&destination))
+            .filter(|path| copies.iter().all(|(_, dst)| dst != path));
+        for path in removed {
+            debug!("Removing {:?}, which is no longer a source", path);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    std::fs::write(state, copied)?;
+
+    Ok(())
+}
+
+/// Resolve the `.` and `..` components of a path without touching the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Copy the files the sources include with `include!`, `include_str!` or `include_bytes!` into
+/// the project, at the same location relative to the copies of the sources made by
+/// `copy_sources`, so the relative paths resolve in the project as they do next to the sources.
+/// With `concat`, every source ended up in `src/main.rs`, so the paths are taken from `src/`.
+pub fn copy_included_files(
+    temp: &Path,
+    sources: &[PathBuf],
+    concat: bool,
+) -> Result<(), CargoPlayError> {
+    let base = match sources.first().and_then(|first| first.parent()) {
+        Some(base) => base,
+        None => return Ok(()),
+    };
+    let mut copied = std::collections::HashMap::new();
+
+    for source in sources {
+        let dir = source.parent().unwrap_or(base);
+        let copied_dir = if concat {
+            temp.join("src")
+        } else {
+            temp.join("src").join(
+                diff_paths(dir, base)
+                    .ok_or_else(|| CargoPlayError::DiffPathError(dir.to_owned()))?,
+            )
+        };
+
+        for include in includes::includes(&std::fs::read_to_string(source)?) {
+            let path = match include.path {
+                Some(path) if Path::new(&path).is_relative() => path,
+                Some(_) => continue,
+                None => {
+                    warn!(
+                        "the path given to `{}!` in {:?} is not a string literal, copy the file \
+                         it refers to with --save and adjust it by hand",
+                        include.macro_name, source
+                    );
+                    continue;
+                }
+            };
+            let file = dir.join(&path);
+            let dst = normalize_path(&copied_dir.join(&path));
+
+            if !file.is_file() {
+                // rustc reports the missing file
+                continue;
+            }
+            if !dst.starts_with(temp) {
+                warn!(
+                    "{:?} included by {:?} can't be placed in the generated project",
+                    path, source
+                );
+                continue;
+            }
+            // concatenated sources from different directories may include different files
+            // through the same path, only one of them fits
+            match copied.get(&dst) {
+                Some(other) if *other != file => {
+                    warn!(
+                        "{:?} included by {:?} is already taken by {:?} in the concatenated \
+                         source",
+                        path, source, other
+                    );
+                    continue;
+                }
+                Some(_) => continue,
+                None => {}
+            }
+
+            if let Some(parent) = dst.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            debug!("Copying {:?} => {:?}", file, dst);
+            std::fs::copy(&file, &dst)?;
+            copied.insert(dst, file);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse dotenv style `KEY=VALUE` lines. Blank lines and `#` comments are skipped, and values
+/// may be wrapped in single or double quotes.
+pub fn parse_env_file(content: &str) -> Result<Vec<(String, String)>, CargoPlayError> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .filter(|(key, _)| !key.is_empty() && !key.contains(char::is_whitespace))
+                .ok_or_else(|| {
+                    CargoPlayError::ParseError(format!(
+                        "line {}: expected KEY=VALUE, found {:?}",
+                        number, line
+                    ))
+                })?;
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+                .unwrap_or(value);
+
+            Ok((key.to_string(), value.to_string()))
         })
-        .map_err(From::from)
+        .collect()
+}
+
+/// The environment variables of the program, loaded with `--env-file` and then set with `--env`
+/// so that the latter win.
+pub fn env_vars(opt: &Opt) -> Result<Vec<(String, String)>, CargoPlayError> {
+    let mut vars = match &opt.env_file {
+        Some(path) => parse_env_file(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    vars.extend(
+        opt.env
+            .iter()
+            .map(|var| (var.name.clone(), var.value.clone())),
+    );
+
+    Ok(vars)
+}
+
+/// Whether `binary` was built after every file in `sources` was last modified. A missing file
+/// or modification time counts as stale.
+pub fn is_fresh(binary: &Path, sources: &[PathBuf]) -> bool {
+    let modified = |path: &Path| {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+
+    match modified(binary) {
+        Some(built) => sources
+            .iter()
+            .all(|source| modified(source).is_some_and(|changed| changed <= built)),
+        None => false,
+    }
+}
+
+/// Create a `Command` running `program`, optionally prefixed with a sandbox wrapper such as
+/// `firejail` or `bwrap --ro-bind / /`. The program and its arguments are passed to the wrapper.
+pub fn sandboxed_command<S: AsRef<OsStr>>(sandbox: Option<&str>, program: S) -> Command {
+    // FIXME: proper escaping
+    let mut wrapper = sandbox
+        .map(str::split_ascii_whitespace)
+        .into_iter()
+        .flatten();
+
+    match wrapper.next() {
+        Some(first) => {
+            let mut command = Command::new(first);
+            command.args(wrapper).arg(program);
+            command
+        }
+        None => Command::new(program),
+    }
+}
+
+/// Create a `Command` running the built binary directly, as `--arg0` when given.
+pub fn program_command(opt: &Opt, binary: &Path) -> Command {
+    #[allow(unused_mut)]
+    let mut command = sandboxed_command(opt.sandbox.as_deref(), binary);
+
+    // a sandbox wrapper would get the name instead of the binary
+    #[cfg(unix)]
+    if let (Some(arg0), None) = (&opt.arg0, &opt.sandbox) {
+        use std::os::unix::process::CommandExt;
+        command.arg0(arg0);
+    }
+
+    command
+}
+
+/// Run the `--on-success` hook through the shell. A failing hook is only reported, the program
+/// already succeeded.
+pub fn run_on_success(
+    opt: &Opt,
+    hook: &str,
+    project: &Path,
+    package: &str,
+) -> Result<(), CargoPlayError> {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command
+        .arg(hook)
+        .env("CARGO_PLAY_PROJECT", project)
+        .stdin(Stdio::null());
+    if opt.lib_name().is_none() {
+        command.env("CARGO_PLAY_BINARY", binary_path(opt, project, package));
+    }
+
+    debug!("Running {:?}", command);
+    let status = command.status()?;
+    if !status.success() {
+        warn!("--on-success hook failed with {}", status);
+    }
+
+    Ok(())
+}
+
+/// Copy the `--main` binary next to the library at `src/main.rs`.
+pub fn copy_main(temp: &Path, main: &Path) -> Result<(), CargoPlayError> {
+    let dst = temp.join("src").join("main.rs");
+    debug!("Copying {:?} => {:?}", main, dst);
+    std::fs::copy(main, dst)?;
+
+    Ok(())
+}
+
+/// Write a single crate root made of all the sources concatenated, in place of copying them.
+pub fn write_concatenated(temp: &Path, files: &[String], lib: bool) -> Result<(), CargoPlayError> {
+    let destination = temp.join("src");
+    let _ = std::fs::remove_dir_all(&destination);
+    std::fs::create_dir_all(&destination)?;
+
+    let root = destination.join(if lib { "lib.rs" } else { "main.rs" });
+    debug!("Writing concatenated sources to {:?}", root);
+    std::fs::write(root, concat_sources(files))?;
+
+    Ok(())
+}
+
+/// Generate a `fn main` calling the top-level function `name` of `source`, see `--main-fn`.
+pub fn main_fn_wrapper(source: &str, name: &str) -> Result<String, CargoPlayError> {
+    let invalid = |reason: &str| CargoPlayError::InvalidMainFn(name.into(), reason.into());
+    let file = syn::parse_file(source)?;
+    let functions: Vec<&syn::ItemFn> = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Fn(function) => Some(function),
+            _ => None,
+        })
+        .collect();
+
+    if functions
+        .iter()
+        .any(|function| function.sig.ident == "main")
+    {
+        return Err(invalid("the source already defines `fn main`"));
+    }
+
+    let function = functions
+        .into_iter()
+        .find(|function| function.sig.ident == name)
+        .ok_or_else(|| invalid("no such top-level function"))?;
+    let sig = &function.sig;
+
+    if !sig.inputs.is_empty() {
+        return Err(invalid("the function must not take arguments"));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(invalid("the function must not be generic"));
+    }
+    if sig.asyncness.is_some() {
+        return Err(invalid("the function must not be async"));
+    }
+
+    let returns_unit = match &sig.output {
+        syn::ReturnType::Default => true,
+        syn::ReturnType::Type(_, ty) => match ty.as_ref() {
+            syn::Type::Tuple(tuple) => tuple.elems.is_empty(),
+            _ => false,
+        },
+    };
+
+    Ok(if returns_unit {
+        format!("\nfn main() {{\n    {}();\n}}\n", name)
+    } else {
+        format!(
+            "\nfn main() {{\n    println!(\"{{:?}}\", {}());\n}}\n",
+            name
+        )
+    })
+}
+
+/// Append the `--main-fn` wrapper to the binary root of the project.
+pub fn append_main_fn(temp: &Path, name: &str) -> Result<(), CargoPlayError> {
+    let root = temp.join("src").join("main.rs");
+    let mut source = std::fs::read_to_string(&root)?;
+    let wrapper = main_fn_wrapper(&source, name)?;
+
+    source.push_str(&wrapper);
+    std::fs::write(root, source)?;
+
+    Ok(())
+}
+
+/// Turn `source` into a test, see `--run-as-test`. A `fn main` is called from a generated
+/// `#[test]`; otherwise the whole source is taken as the body of the test.
+pub fn run_as_test_wrapper(source: &str) -> String {
+    let main = syn::parse_file(source).ok().and_then(|file| {
+        file.items.into_iter().find_map(|item| match item {
+            syn::Item::Fn(function) if function.sig.ident == "main" => Some(function.sig.output),
+            _ => None,
+        })
+    });
+
+    match main {
+        Some(syn::ReturnType::Default) => {
+            format!("{}\n#[test]\nfn run_as_test() {{\n    main()\n}}\n", source)
+        }
+        Some(output) => format!(
+            "{}\n#[test]\nfn run_as_test() {} {{\n    main()\n}}\n",
+            source,
+            quote::quote!(#output)
+        ),
+        None => format!("#[test]\nfn run_as_test() {{\n{}\n}}\n", source),
+    }
+}
+
+/// Rewrite the binary root of the project as a test, see `--run-as-test`.
+pub fn wrap_as_test(temp: &Path) -> Result<(), CargoPlayError> {
+    let root = temp.join("src").join("main.rs");
+    let source = std::fs::read_to_string(&root)?;
+    std::fs::write(root, run_as_test_wrapper(&source))?;
+
+    Ok(())
+}
+
+/// Whether any of the items is a function with the given attribute, e.g. `#[test]`, including
+/// in inline modules.
+fn has_fns_with(items: &[syn::Item], attribute: &str) -> bool {
+    items.iter().any(|item| match item {
+        syn::Item::Fn(function) => function
+            .attrs
+            .iter()
+            .any(|attr| attr.path.is_ident(attribute)),
+        syn::Item::Mod(module) => module
+            .content
+            .as_ref()
+            .is_some_and(|(_, items)| has_fns_with(items, attribute)),
+        _ => false,
+    })
+}
+
+/// Whether the source has `#[bench]` functions for the libtest harness.
+pub fn has_benches(source: &str) -> bool {
+    syn::parse_file(source).is_ok_and(|file| has_fns_with(&file.items, "bench"))
+}
+
+fn has_main(items: &[syn::Item]) -> bool {
+    items.iter().any(|item| match item {
+        syn::Item::Fn(function) => function.sig.ident == "main",
+        _ => false,
+    })
+}
+
+/// Whether the source only holds `#[test]` functions to run, without a `fn main`. Such snippets
+/// are built as a library so their tests run under the test harness.
+pub fn is_tests_only(source: &str) -> bool {
+    let file = match syn::parse_file(source) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+
+    !has_main(&file.items) && has_fns_with(&file.items, "test")
+}
+
+/// Whether the source has no `fn main`, which makes it a library member of a `--workspace`.
+/// Sources that can't be parsed are left to rustc as binaries.
+pub fn is_library(source: &str) -> bool {
+    syn::parse_file(source).is_ok_and(|file| !has_main(&file.items))
+}
+
+/// Whether the snippet given to cargo-play should be built as a library to run its tests, see
+/// `is_tests_only`.
+pub fn runs_tests_only(opt: &Opt) -> Result<bool, CargoPlayError> {
+    if opt.lib || opt.main_fn.is_some() || opt.run_as_test || opt.existing_project().is_some() {
+        return Ok(false);
+    }
+
+    let sources = parse_inputs(&opt.src[..1])?;
+    Ok(is_tests_only(&sources[0]))
+}
+
+/// Attribute wiring up the async runtime found among the dependencies.
+fn runtime_attribute(dependencies: &[String]) -> Option<&'static str> {
+    let has = |name: &str| {
+        dependencies
+            .iter()
+            .any(|dependency| dependency.replace("-", "_") == name)
+    };
+
+    if has("tokio") {
+        Some("#[tokio::main]")
+    } else if has("async_std") {
+        Some("#[async_std::main]")
+    } else {
+        None
+    }
+}
+
+/// Add the runtime attribute to an `async fn main` that has none. Returns `None` when the source
+/// doesn't need it, or can't be parsed so rustc reports the problem instead.
+pub fn add_async_runtime(
+    source: &str,
+    dependencies: &[String],
+) -> Result<Option<String>, CargoPlayError> {
+    use syn::spanned::Spanned;
+
+    let file = match syn::parse_file(source) {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+    let main = file.items.iter().find_map(|item| match item {
+        syn::Item::Fn(function)
+            if function.sig.ident == "main"
+                && function.sig.asyncness.is_some()
+                && !function.attrs.iter().any(|attr| {
+                    attr.path
+                        .segments
+                        .last()
+                        .is_some_and(|segment| segment.ident == "main")
+                }) =>
+        {
+            Some(function)
+        }
+        _ => None,
+    });
+
+    let main = match main {
+        Some(main) => main,
+        None => return Ok(None),
+    };
+
+    let attribute = runtime_attribute(dependencies).ok_or(CargoPlayError::NoAsyncRuntime)?;
+    // below the other attributes, on the line the item itself starts
+    let line = match &main.vis {
+        syn::Visibility::Inherited => main.sig.span(),
+        vis => vis.span(),
+    }
+    .start()
+    .line;
+    let mut wired = String::with_capacity(source.len() + attribute.len() + 1);
+
+    for (number, text) in (1..).zip(source.lines()) {
+        if number == line {
+            wired.push_str(attribute);
+            wired.push('\n');
+        }
+        wired.push_str(text);
+        wired.push('\n');
+    }
+
+    Ok(Some(wired))
+}
+
+/// Return type given to a `fn main` that uses `?` without declaring one.
+const MAIN_RESULT: &str = "Result<(), Box<dyn std::error::Error>>";
+
+/// Whether the tokens use the `?` operator, including in nested groups. Used for the arguments
+/// of macros, which aren't parsed.
+fn uses_try(tokens: proc_macro2::TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Punct(punct) => punct.as_char() == '?',
+        proc_macro2::TokenTree::Group(group) => uses_try(group.stream()),
+        _ => false,
+    })
+}
+
+/// What the body of `fn main` does that matters to `add_main_result`. Closures, async blocks and
+/// nested items return on their own, so they are skipped.
+#[derive(Default)]
+struct MainBody {
+    uses_try: bool,
+    /// End of the `return` keyword of each `return;`
+    bare_returns: Vec<proc_macro2::LineColumn>,
+}
+
+impl<'ast> syn::visit::Visit<'ast> for MainBody {
+    fn visit_expr_try(&mut self, expr: &'ast syn::ExprTry) {
+        self.uses_try = true;
+        syn::visit::visit_expr_try(self, expr);
+    }
+
+    fn visit_expr_return(&mut self, expr: &'ast syn::ExprReturn) {
+        if expr.expr.is_none() {
+            self.bare_returns.push(expr.return_token.span.end());
+        }
+        syn::visit::visit_expr_return(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        self.uses_try |= uses_try(mac.tokens.clone());
+    }
+
+    fn visit_expr_closure(&mut self, _: &'ast syn::ExprClosure) {}
+
+    fn visit_expr_async(&mut self, _: &'ast syn::ExprAsync) {}
+
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+}
+
+/// Byte offset of a span location in `source`.
+fn byte_offset(source: &str, location: proc_macro2::LineColumn) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(location.line - 1)
+        .map(str::len)
+        .sum();
+
+    source[line_start..]
+        .char_indices()
+        .nth(location.column)
+        .map_or(source.len(), |(offset, _)| line_start + offset)
+}
+
+/// Make a `fn main` using `?` without a return type return a `Result`, ending it with `Ok(())`.
+/// Returns `None` when the source doesn't need it, or can't be parsed so rustc reports the
+/// problem instead.
+pub fn add_main_result(source: &str) -> Option<String> {
+    let file = syn::parse_file(source).ok()?;
+    let main = file.items.iter().find_map(|item| match item {
+        syn::Item::Fn(function) if function.sig.ident == "main" => Some(function),
+        _ => None,
+    })?;
+
+    if !matches!(main.sig.output, syn::ReturnType::Default) {
+        return None;
+    }
+    let mut body = MainBody::default();
+    syn::visit::Visit::visit_block(&mut body, &main.block);
+    if !body.uses_try {
+        return None;
+    }
+
+    let signature_end = byte_offset(source, main.sig.paren_token.span.end());
+    // the closing brace starts one column before the end of the block
+    let mut block_end = main.block.brace_token.span.end();
+    block_end.column -= 1;
+    let block_end = byte_offset(source, block_end);
+    // a tail expression has to become a statement to be followed by `Ok(())`
+    let tail = match main.block.stmts.last() {
+        Some(syn::Stmt::Expr(_)) => ";",
+        _ => "",
+    };
+
+    // `return;` has to return the `Ok(())` as well, starting from the last one so the offsets
+    // of the others still hold
+    let mut returns: Vec<usize> = body
+        .bare_returns
+        .iter()
+        .map(|location| byte_offset(source, *location))
+        .collect();
+    returns.sort_unstable();
+    let mut body = source[signature_end..block_end].to_string();
+    for offset in returns.into_iter().rev() {
+        body.insert_str(offset - signature_end, " Ok(())");
+    }
+
+    Some(format!(
+        "{} -> {}{}{}\n    Ok(())\n{}",
+        &source[..signature_end],
+        MAIN_RESULT,
+        body.trim_end(),
+        tail,
+        &source[block_end..]
+    ))
+}
+
+/// Give the `fn main` of the binary root a `Result` return type when it uses `?`, see
+/// `add_main_result`.
+pub fn wire_main_result(temp: &Path) -> Result<(), CargoPlayError> {
+    let root = temp.join("src").join("main.rs");
+
+    if let Some(source) = add_main_result(&std::fs::read_to_string(&root)?) {
+        debug!("Making `fn main` of {:?} return a Result", root);
+        std::fs::write(root, source)?;
+    }
+
+    Ok(())
+}
+
+/// Add the runtime attribute to the `async fn main` of the binary root, based on the dependencies
+/// of the generated manifest.
+pub fn wire_async_main(temp: &Path, manifest: &[u8]) -> Result<(), CargoPlayError> {
+    let root = temp.join("src").join("main.rs");
+    let manifest: Table = toml::from_slice(manifest).map_err(CargoPlayError::from_serde)?;
+    let dependencies: Vec<String> = match manifest.get("dependencies") {
+        Some(Value::Table(dependencies)) => dependencies.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+
+    if let Some(source) = add_async_runtime(&std::fs::read_to_string(&root)?, &dependencies)? {
+        debug!("Adding async runtime attribute to {:?}", root);
+        std::fs::write(root, source)?;
+    }
+
+    Ok(())
+}
+
+/// Whether the source defines a `#[global_allocator]` of its own.
+pub fn defines_global_allocator(source: &str) -> bool {
+    match syn::parse_file(source) {
+        Ok(file) => file.items.iter().any(|item| match item {
+            syn::Item::Static(item) => item
+                .attrs
+                .iter()
+                .any(|attr| attr.path.is_ident("global_allocator")),
+            _ => false,
+        }),
+        Err(_) => source.contains("#[global_allocator]"),
+    }
+}
+
+/// The `#[global_allocator]` static selecting `allocator`.
+pub fn allocator_static(allocator: Allocator) -> String {
+    format!(
+        "\n#[global_allocator]\nstatic GLOBAL: {0} = {0};\n",
+        allocator.type_path()
+    )
+}
+
+/// Append the `--allocator` static to the crate root of the project.
+pub fn inject_allocator(temp: &Path, allocator: Allocator) -> Result<(), CargoPlayError> {
+    let src = temp.join("src");
+    let root = match src.join("main.rs") {
+        main if main.exists() => main,
+        _ => src.join("lib.rs"),
+    };
+    let mut source = std::fs::read_to_string(&root)?;
+
+    debug!("Adding {:?} global allocator to {:?}", allocator, root);
+    source.push_str(&allocator_static(allocator));
+    std::fs::write(root, source)?;
+
+    Ok(())
+}
+
+/// Reject the sources using macros whose crate is declared without the feature providing them,
+/// see `--pre-expand-macros`.
+pub fn check_macro_providers(files: &[String], manifest: &[u8]) -> Result<(), CargoPlayError> {
+    let manifest: Table = toml::from_slice(manifest).map_err(CargoPlayError::from_serde)?;
+    let mut dependencies = Table::new();
+    for section in &["dependencies", "dev-dependencies"] {
+        if let Some(Value::Table(section)) = manifest.get(*section) {
+            dependencies.extend(section.clone());
+        }
+    }
+
+    let hints: Vec<String> = files
+        .iter()
+        .flat_map(|file| macros::missing_providers(file, &dependencies))
+        .collect();
+
+    if hints.is_empty() {
+        Ok(())
+    } else {
+        Err(CargoPlayError::MissingMacroProviders(hints.join("\n")))
+    }
+}
+
+/// Reuse the lockfile of the project given with `--from-project`, if it has one.
+pub fn copy_project_lock(temp: &Path, project: &Path) -> Result<(), CargoPlayError> {
+    let lock = project.join("Cargo.lock");
+
+    if lock.is_file() {
+        debug!("Copying {:?} => {:?}", lock, temp);
+        std::fs::copy(lock, temp.join("Cargo.lock"))?;
+    }
+
+    Ok(())
+}
+
+/// Copy integration test files into the `tests/` directory of the temporary project.
+pub fn copy_test_files(temp: &Path, files: &[PathBuf]) -> Result<(), CargoPlayError> {
+    let destination = temp.join("tests");
+
+    // remove tests left over from a previous run
+    let _ = std::fs::remove_dir_all(&destination);
+
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(&destination)?;
+
+    for file in files {
+        let name = file
+            .file_name()
+            .ok_or_else(|| CargoPlayError::DiffPathError(file.to_owned()))?;
+        let dst = destination.join(name);
+
+        debug!("Copying {:?} => {:?}", file, dst);
+        std::fs::copy(file, dst)?;
+    }
+
+    Ok(())
+}
+
+/// Container runtimes tried by `--container`, in order of preference.
+const CONTAINER_RUNTIMES: &[&str] = &["docker", "podman"];
+
+/// The first container runtime installed, looked up once.
+pub fn container_runtime() -> Result<&'static str, CargoPlayError> {
+    static RUNTIME: OnceLock<Option<&'static str>> = OnceLock::new();
+
+    RUNTIME
+        .get_or_init(|| {
+            CONTAINER_RUNTIMES
+                .iter()
+                .copied()
+                .find(|runtime| installed(runtime))
+        })
+        .ok_or(CargoPlayError::NoContainerRuntime)
+}
+
+/// Create a `Command` running cargo in a container of `image`, with the project mounted at the
+/// same path so paths given to cargo stay valid, and the `env` variables passed through.
+fn container_command(runtime: &str, image: &str, project: &Path, env: &[String]) -> Command {
+    let mut mount = project.as_os_str().to_owned();
+    mount.push(":");
+    mount.push(project);
+
+    let mut command = Command::new(runtime);
+    command
+        .args(["run", "--rm", "-i", "-v"])
+        .arg(mount)
+        .arg("-w")
+        .arg(project);
+    for name in env {
+        command.arg("-e").arg(name);
+    }
+    command.arg(image).arg("cargo");
+
+    command
+}
+
+/// Start a cargo invocation of `subcommand` on the generated project, running where the build
+/// runs and with its environment.
+fn project_command(opt: &Opt, project: &Path, subcommand: &[&str]) -> Command {
+    let mut cargo = match &opt.container {
+        Some(image) => {
+            // the variables set on the runtime below have to be passed on to the container
+            let mut env = vec![String::from("RUSTFLAGS")];
+            env.extend(
+                env_vars(opt)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(name, _)| name),
+            );
+            let runtime = container_runtime().unwrap_or(CONTAINER_RUNTIMES[0]);
+            container_command(runtime, image, project, &env)
+        }
+        None => sandboxed_command(opt.sandbox.as_deref(), "cargo"),
+    };
+
+    if let Some(toolchain) = &opt.toolchain {
+        cargo.arg(format!("+{}", toolchain));
+    }
+
+    if let Some(rustflags) = opt.rustflags() {
+        cargo.env("RUSTFLAGS", rustflags);
+    }
+
+    if let Some(dir) = &opt.target_dir {
+        cargo.env("CARGO_TARGET_DIR", dir);
+    }
+
+    // seen by the build as well as by the program `cargo run` starts
+    cargo.envs(opt.env.iter().map(|var| (&var.name, &var.value)));
+
+    cargo
+        .args(subcommand)
+        .arg("--manifest-path")
+        .arg(project.join("Cargo.toml"));
+
+    cargo
+}
+
+/// Add the flags deciding how the project's dependencies are resolved, which every command
+/// looking at them has to agree on with the build.
+fn resolution_flags(opt: &Opt, cargo: &mut Command) {
+    if let Some(target) = &opt.target {
+        cargo.arg("--target").arg(target);
+    }
+
+    if opt.quiet {
+        cargo.arg("--quiet");
+    }
+
+    for (set, flag) in [
+        (opt.offline, "--offline"),
+        (opt.frozen, "--frozen"),
+        (opt.locked, "--locked"),
+    ] {
+        if set {
+            cargo.arg(flag);
+        }
+    }
+}
+
+/// Start a cargo invocation of `subcommand` building the generated project, with the options
+/// shared by every such subcommand.
+fn base_command(opt: &Opt, project: &Path, subcommand: &[&str]) -> Command {
+    let mut cargo = project_command(opt, project, subcommand);
+
+    if let Some(cargo_option) = &opt.cargo_option {
+        // FIXME: proper escaping
+        cargo.args(cargo_option.split_ascii_whitespace());
+    }
+
+    if opt.release {
+        cargo.arg("--release");
+    }
+
+    resolution_flags(opt, &mut cargo);
+
+    if let Some(bin) = &opt.bin {
+        cargo.arg("--bin").arg(bin);
+    }
+
+    cargo
+}
+
+/// Construct the cargo invocation for the generated project, without running it.
+pub fn cargo_command(opt: &Opt, project: &Path) -> Command {
+    let action = opt.cargo_action();
+    let mut cargo = base_command(opt, project, &[action.subcommand()]);
+
+    if opt.quiet_on_success && !opt.quiet {
+        // the project has already been built successfully by `prebuild` at this point
+        cargo.arg("--quiet");
+    }
+
+    if action.forwards_args() && !opt.args.is_empty() {
+        cargo.arg("--").args(&opt.args);
+    }
+
+    cargo
+}
+
+/// Construct a cargo invocation that only compiles the generated project.
+pub fn build_command(opt: &Opt, project: &Path) -> Command {
+    base_command(opt, project, opt.cargo_action().build_subcommand())
+}
+
+/// Check whether `cargo <subcommand>` is available, either built in or installed.
+fn has_cargo_subcommand(opt: &Opt, subcommand: &str) -> Result<bool, CargoPlayError> {
+    let mut cargo = Command::new("cargo");
+
+    if let Some(toolchain) = &opt.toolchain {
+        cargo.arg(format!("+{}", toolchain));
+    }
+
+    let list = cargo.arg("--list").stderr(Stdio::null()).output()?;
+
+    Ok(String::from_utf8_lossy(&list.stdout)
+        .lines()
+        .any(|line| line.split_whitespace().next() == Some(subcommand)))
+}
+
+/// Copy everything the program writes to `stderr` to our own stderr, returning a copy of it.
+fn tee_stderr<R: Read>(mut stderr: R) -> Result<String, CargoPlayError> {
+    let mut captured = Vec::new();
+    let mut buf = [0; 4096];
+
+    loop {
+        let read = stderr.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        std::io::stderr().write_all(&buf[..read])?;
+        captured.extend_from_slice(&buf[..read]);
+    }
+
+    Ok(String::from_utf8_lossy(&captured).into_owned())
+}
+
+/// Print hints for the diagnostics of a failed build, see `--explain-error`.
+fn explain_failure(project: &Path, diagnostics: &str) {
+    let edition = read_project_edition(project).unwrap_or_default();
+
+    for hint in explain::hints(diagnostics, edition) {
+        info!("hint: {}", hint);
+    }
+}
+
+/// Compile the project on its own before running it, when the build has to be told apart from
+/// the run: to hide its output with `--quiet-on-success`, to report build failures with their
+/// own exit code with `--wrap-errors`, to run the binary directly with `--run-count`, or to
+/// explain its failures with `--explain-error`. Returns `None` when no separate build is needed.
+pub fn prebuild(opt: &Opt, project: &Path) -> Result<Option<ExitStatus>, CargoPlayError> {
+    if !opt.quiet_on_success
+        && !opt.wrap_errors
+        && opt.run_count.is_none()
+        && opt.output_format != OutputFormat::Json
+        && !opt.explain_error
+    {
+        return Ok(None);
+    }
+
+    let mut build = build_command(opt, project);
+
+    if !opt.quiet_on_success && !opt.explain_error {
+        return build.status().map(Some).map_err(From::from);
+    }
+
+    // the diagnostics are captured, keep them in colour when they end up on a terminal
+    if env::var_os("CARGO_TERM_COLOR").is_none() && std::io::stderr().is_terminal() {
+        build.env("CARGO_TERM_COLOR", "always");
+    }
+
+    let (status, diagnostics) = if opt.quiet_on_success {
+        let build = build
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()?;
+        if !build.status.success() {
+            std::io::stderr().write_all(&build.stderr)?;
+        }
+        (
+            build.status,
+            String::from_utf8_lossy(&build.stderr).into_owned(),
+        )
+    } else {
+        let mut child = build.stderr(Stdio::piped()).spawn()?;
+        let diagnostics = child.stderr.take().map(tee_stderr).transpose()?;
+        (child.wait()?, diagnostics.unwrap_or_default())
+    };
+
+    if !status.success() && opt.explain_error {
+        explain_failure(project, &diagnostics);
+    }
+
+    Ok(Some(status))
+}
+
+/// Run the already built binary `count` times, reporting the wall-clock time of each run and a
+/// summary on stderr. Stops at the first failing run unless `--run-all` is given.
+pub fn run_repeatedly(
+    opt: &Opt,
+    project: &Path,
+    package: &str,
+    count: usize,
+) -> Result<ExitStatus, CargoPlayError> {
+    let binary = binary_path(opt, project, package);
+    let env = env_vars(opt)?;
+    let mut timings = Vec::with_capacity(count);
+    let mut last = None;
+
+    for run in 1..=count {
+        let start = std::time::Instant::now();
+        let status = program_command(opt, &binary)
+            .args(&opt.args)
+            .envs(env.iter().cloned())
+            .stderr(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .status()?;
+        let elapsed = start.elapsed();
+
+        info!("run {}/{}: {:.2?} ({})", run, count, elapsed, status);
+        timings.push(elapsed);
+        last = Some(status);
+
+        if !status.success() && !opt.run_all {
+            break;
+        }
+    }
+
+    let total: std::time::Duration = timings.iter().sum();
+    info!(
+        "{} runs in {:.2?}: mean {:.2?}, min {:.2?}, max {:.2?}",
+        timings.len(),
+        total,
+        total / timings.len() as u32,
+        timings.iter().min().copied().unwrap_or_default(),
+        timings.iter().max().copied().unwrap_or_default(),
+    );
+
+    last.ok_or_else(|| CargoPlayError::ParseError("--run-count must be positive".into()))
+}
+
+pub fn run_cargo_build(opt: &Opt, project: &Path) -> Result<ExitStatus, CargoPlayError> {
+    let action = opt.cargo_action();
+
+    if let Some(tool) = action.external_subcommand() {
+        if !has_cargo_subcommand(opt, action.subcommand())? {
+            return Err(CargoPlayError::SubcommandNotFound(tool.into()));
+        }
+    }
+
+    let mut cargo = cargo_command(opt, project);
+    cargo.envs(env_vars(opt)?).stderr(Stdio::inherit());
+
+    if !opt.pty {
+        return cargo.stdout(Stdio::inherit()).status().map_err(From::from);
+    }
+
+    let pty = Pty::open()?;
+    let mut child = cargo.stdout(pty.slave_stdio()?).spawn()?;
+    // the command holds on to its copy of the terminal until dropped
+    drop(cargo);
+    let output = pty.forward_output();
+    let status = child.wait()?;
+    let _ = output.join();
+
+    Ok(status)
+}
+
+/// The target directory of the generated project, unless cargo is told to use another one
+/// with `--target-dir` or through `CARGO_TARGET_DIR`.
+pub fn target_dir(opt: &Opt, project: &Path) -> PathBuf {
+    let dir = opt
+        .target_dir
+        .clone()
+        .map(PathBuf::into_os_string)
+        .or_else(|| env::var_os("CARGO_TARGET_DIR"));
+
+    match dir {
+        Some(dir) => env::current_dir()
+            .map(|cwd| cwd.join(&dir))
+            .unwrap_or_else(|_| dir.into()),
+        None => project.join("target"),
+    }
+}
+
+/// Whether `program` can be run, checked with `program --version`.
+fn installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// Debuggers tried by `--debug-run`, with the Rust wrapper adding pretty printers and the
+/// arguments separating the program and its arguments from the debugger's own.
+#[cfg(target_os = "macos")]
+const DEBUGGERS: &[(&str, &str, &str)] =
+    &[("lldb", "rust-lldb", "--"), ("gdb", "rust-gdb", "--args")];
+#[cfg(not(target_os = "macos"))]
+const DEBUGGERS: &[(&str, &str, &str)] =
+    &[("gdb", "rust-gdb", "--args"), ("lldb", "rust-lldb", "--")];
+
+/// Create the `Command` starting `binary` under `program`, the `debugger` or its Rust wrapper,
+/// inside the `--sandbox` and with the `--env` variables. `--arg0` goes through the debugger's
+/// settings, the debugger itself keeps its name.
+fn debugger_command(
+    opt: &Opt,
+    debugger: &str,
+    program: &str,
+    separator: &str,
+    binary: &Path,
+) -> Result<Command, CargoPlayError> {
+    let mut command = sandboxed_command(opt.sandbox.as_deref(), program);
+    if let Some(arg0) = &opt.arg0 {
+        let (flag, setting) = match debugger {
+            "lldb" => ("-O", format!("settings set target.arg0 {}", arg0)),
+            // gdb starts the program through the shell, which renames it
+            _ => (
+                "-iex",
+                format!("set exec-wrapper exec -a '{}'", arg0.replace('\'', "'\\''")),
+            ),
+        };
+        command.args([flag, &setting]);
+    }
+    command
+        .arg(separator)
+        .arg(binary)
+        .args(&opt.args)
+        .envs(env_vars(opt)?);
+    Ok(command)
+}
+
+/// Build the project, then start the binary under the first debugger installed, see
+/// `--debug-run`.
+pub fn debug_run(opt: &Opt, project: &Path, package: &str) -> Result<ExitStatus, CargoPlayError> {
+    let build = build_command(opt, project).status()?;
+    if !build.success() {
+        return Ok(build);
+    }
+
+    let (debugger, wrapper, separator) = DEBUGGERS
+        .iter()
+        .find(|(debugger, _, _)| installed(debugger))
+        .ok_or(CargoPlayError::NoDebugger)?;
+    let program = if installed(wrapper) {
+        wrapper
+    } else {
+        debugger
+    };
+
+    debug!("Debugging with {}", program);
+    debugger_command(
+        opt,
+        debugger,
+        program,
+        separator,
+        &binary_path(opt, project, package),
+    )?
+    .status()
+    .map_err(From::from)
+}
+
+/// Install the `--ensure-components` and `--ensure-targets` with rustup, for the selected
+/// toolchain. Already installed ones are left alone by rustup.
+pub fn ensure_rustup_installs(opt: &Opt) -> Result<(), CargoPlayError> {
+    let installs = [
+        ("component", &opt.ensure_components),
+        ("target", &opt.ensure_targets),
+    ];
+
+    for (kind, names) in installs.iter().filter(|(_, names)| !names.is_empty()) {
+        let mut rustup = Command::new("rustup");
+        rustup.args([kind, "add"]).args(names.iter());
+        if let Some(toolchain) = &opt.toolchain {
+            rustup.args(["--toolchain", toolchain]);
+        }
+
+        debug!("Running {:?}", rustup);
+        let status = rustup.status().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                CargoPlayError::NoRustup(format!("install {}", names.join(", ")))
+            }
+            _ => e.into(),
+        })?;
+        if !status.success() {
+            return Err(CargoPlayError::CommandFailed(
+                format!("rustup {} add", kind),
+                status,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The target directory shared by all snippets with `--fast`.
+pub fn shared_target_dir() -> PathBuf {
+    temp_dir("cargo-play-target".into())
+}
+
+/// The directory holding the artifacts of the generated project for the selected profile, under
+/// a directory of its own for a `--target`.
+pub fn profile_dir(opt: &Opt, project: &Path) -> PathBuf {
+    let profile = if opt.release { "release" } else { "debug" };
+    let mut dir = target_dir(opt, project);
+    if let Some(target) = &opt.target {
+        dir.push(target);
+    }
+    dir.join(profile)
+}
+
+/// The binary built for the generated project, named after the `--bin` target or the package.
+pub fn binary_path(opt: &Opt, project: &Path, package: &str) -> PathBuf {
+    let name = opt.bin.clone().unwrap_or_else(|| package.to_string());
+    let suffix = match &opt.target {
+        Some(target) if target.contains("windows") => ".exe",
+        Some(_) => "",
+        None => env::consts::EXE_SUFFIX,
+    };
+    profile_dir(opt, project).join(format!("{}{}", name, suffix))
+}
+
+/// Copy the built binary, or the library with `--lib`, into `out_dir`.
+pub fn export_artifacts(
+    opt: &Opt,
+    project: &Path,
+    package: &str,
+    out_dir: &Path,
+) -> Result<(), CargoPlayError> {
+    std::fs::create_dir_all(out_dir)?;
+
+    let artifact = match opt.lib_name() {
+        Some(lib) => {
+            // tests don't leave the library artifact under target/, build it explicitly
+            let build = base_command(opt, project, &["build", "--lib"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .output()?;
+            if !build.status.success() {
+                std::io::stderr().write_all(&build.stderr)?;
+                return Err(CargoPlayError::LibraryBuildError);
+            }
+            profile_dir(opt, project).join(format!("lib{}.rlib", lib))
+        }
+        None => binary_path(opt, project, package),
+    };
+
+    let destination = out_dir.join(artifact.file_name().unwrap_or_default());
+    debug!("Copying {:?} => {:?}", artifact, destination);
+    std::fs::copy(&artifact, &destination)?;
+
+    if opt.strip && opt.lib_name().is_none() {
+        if opt.release {
+            strip_binary(&destination)?;
+        } else {
+            warn!("--strip only applies to --release builds, skipping");
+        }
+    }
+
+    Ok(())
+}
+
+/// Strip the symbols of an exported binary with the `strip` tool and report the size saved.
+fn strip_binary(binary: &Path) -> Result<(), CargoPlayError> {
+    let before = std::fs::metadata(binary)?.len();
+    let status = Command::new("strip").arg(binary).status()?;
+
+    if !status.success() {
+        return Err(CargoPlayError::CommandFailed("strip".into(), status));
+    }
+
+    let after = std::fs::metadata(binary)?.len();
+    info!(
+        "Stripped {}: {} -> {} bytes ({:.1}% smaller)",
+        binary.display(),
+        before,
+        after,
+        before.saturating_sub(after) as f64 * 100.0 / before.max(1) as f64
+    );
+
+    Ok(())
+}
+
+/// Find the files emitted with `--emit` for the crates of the generated project, i.e. the package
+/// itself, its `--lib` library and its `--bin` binary.
+pub fn emitted_files(
+    opt: &Opt,
+    project: &Path,
+    package: &str,
+) -> Result<Vec<PathBuf>, CargoPlayError> {
+    let emit = match opt.emit {
+        Some(emit) => emit,
+        None => return Ok(Vec::new()),
+    };
+    let extension = emit.extension();
+    let crates: Vec<String> = iter::once(package.to_string())
+        .chain(opt.lib_name())
+        .chain(opt.bin.clone())
+        .map(|name| format!("{}{}-", emit.prefix(), name.replace("-", "_")))
+        .collect();
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(profile_dir(opt, project).join("deps"))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            crates
+                .iter()
+                .any(|prefix| name.starts_with(prefix.as_str()))
+        })
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+/// How a source of `--separate`, or a member of `--workspace`, fared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchOutcome {
+    Succeeded,
+    /// With the exit code of the snippet, when it got to run
+    Failed(Option<i32>),
+    /// Left out after a failure
+    Skipped,
+}
+
+impl From<i32> for BatchOutcome {
+    fn from(exit_code: i32) -> Self {
+        match exit_code {
+            0 => BatchOutcome::Succeeded,
+            code => BatchOutcome::Failed(Some(code)),
+        }
+    }
+}
+
+/// The summary of `--separate` runs or of a `--workspace` build, one line per source or member
+/// with its outcome, then the totals.
+pub fn batch_summary<T: std::fmt::Display>(results: &[(T, BatchOutcome)]) -> Vec<String> {
+    let count = |outcome: fn(BatchOutcome) -> bool| {
+        results
+            .iter()
+            .filter(|(_, result)| outcome(*result))
+            .count()
+    };
+    let mut summary: Vec<String> = results
+        .iter()
+        .map(|(name, outcome)| match outcome {
+            BatchOutcome::Succeeded => format!("    ok       {}", name),
+            BatchOutcome::Failed(Some(code)) => {
+                format!("    failed   {} (exit code {})", name, code)
+            }
+            BatchOutcome::Failed(None) => format!("    failed   {}", name),
+            BatchOutcome::Skipped => format!("    skipped  {}", name),
+        })
+        .collect();
+
+    summary.push(format!(
+        "{} succeeded, {} failed, {} skipped",
+        count(|outcome| outcome == BatchOutcome::Succeeded),
+        count(|outcome| matches!(outcome, BatchOutcome::Failed(_))),
+        count(|outcome| outcome == BatchOutcome::Skipped),
+    ));
+    summary
+}
+
+/// Name of the `--workspace` member generated for `source`, after its file name.
+pub fn member_name(source: &Path) -> String {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    package_name(&stem.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_"))
+}
+
+/// Write the manifest of a `--workspace` with the given members, each in the directory of its
+/// name. Every member is a workspace dependency, which the others use with
+/// `//# name = { workspace = true }`.
+pub fn write_workspace_manifest(dir: &Path, members: &[String]) -> Result<(), CargoPlayError> {
+    let mut dependencies = Table::new();
+    for member in members {
+        let mut spec = Table::new();
+        spec.insert("path".into(), Value::String(member.clone()));
+        dependencies.insert(member.clone(), Value::Table(spec));
+    }
+
+    let mut workspace = Table::new();
+    workspace.insert(
+        "members".into(),
+        Value::Array(members.iter().cloned().map(Value::String).collect()),
+    );
+    workspace.insert("resolver".into(), Value::String("2".into()));
+    workspace.insert("dependencies".into(), Value::Table(dependencies));
+
+    let mut manifest = Table::new();
+    manifest.insert("workspace".into(), Value::Table(workspace));
+    write_cargo_toml(
+        dir,
+        &toml::to_vec(&manifest).map_err(CargoPlayError::from_serde)?,
+    )
+}
+
+/// The outcome of every member of a `--workspace` from the JSON messages of its build: built
+/// members produced an artifact, failed ones an error, and the others were never compiled.
+/// Diagnostics are printed as they come, as cargo would have rendered them.
+pub fn member_outcomes<R: std::io::BufRead>(
+    dir: &Path,
+    members: &[String],
+    messages: R,
+) -> Result<Vec<BatchOutcome>, CargoPlayError> {
+    let mut outcomes = vec![BatchOutcome::Skipped; members.len()];
+    let manifests: Vec<_> = members
+        .iter()
+        .map(|member| dir.join(member).join("Cargo.toml"))
+        .collect();
+
+    for line in messages.lines() {
+        let line = line?;
+        if !line.starts_with('{') {
+            continue;
+        }
+        let message: serde_json::Value =
+            serde_json::from_str(&line).map_err(CargoPlayError::from_serde)?;
+        if let Some(rendered) = message["message"]["rendered"].as_str() {
+            eprint!("{}", rendered);
+        }
+
+        let member = match manifests
+            .iter()
+            .position(|manifest| message["manifest_path"].as_str() == manifest.to_str())
+        {
+            Some(member) => member,
+            None => continue,
+        };
+        match message["reason"].as_str() {
+            Some("compiler-artifact") if outcomes[member] == BatchOutcome::Skipped => {
+                outcomes[member] = BatchOutcome::Succeeded;
+            }
+            Some("compiler-message") if message["message"]["level"] == "error" => {
+                outcomes[member] = BatchOutcome::Failed(None);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(outcomes)
+}
+
+/// Print the dependency tree of the project annotated with the features enabled on each crate,
+/// see `--trace-cargo`.
+pub fn print_feature_tree(opt: &Opt, project: &Path) -> Result<(), CargoPlayError> {
+    if !has_cargo_subcommand(opt, "tree")? {
+        return Err(CargoPlayError::SubcommandNotFound("cargo-tree".into()));
+    }
+
+    let mut cargo = project_command(opt, project, &["tree", "--edges", "features"]);
+    // resolved like the build, so cfg-gated dependencies of the `--target` show up
+    resolution_flags(opt, &mut cargo);
+
+    let status = cargo
+        .stderr(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .status()?;
+
+    if !status.success() {
+        return Err(CargoPlayError::CommandFailed("cargo tree".into(), status));
+    }
+
+    Ok(())
+}
+
+/// Print the versions resolved in the project's `Cargo.lock` for every package except the
+/// project itself, one `name = "version"` line per package.
+pub fn print_deps(project: &Path, package: &str) -> Result<(), CargoPlayError> {
+    let lock = std::fs::read_to_string(project.join("Cargo.lock"))?;
+    let lock: CargoLock = toml::from_str(&lock).map_err(CargoPlayError::from_serde)?;
+
+    for dependency in lock.package.iter().filter(|p| p.name != package) {
+        println!("{} = \"{}\"", dependency.name, dependency.version);
+    }
+
+    Ok(())
+}
+
+/// Copy the directory `from` into `to` recursively, leaving out the top-level entries named in
+/// `skip`.
+fn copy_dir(from: &Path, to: &Path, skip: &[&str]) -> Result<(), CargoPlayError> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if skip.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+
+        let dst = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dst, &[])?;
+        } else {
+            std::fs::copy(entry.path(), dst)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the generated project to the `--save` destination, without its build artifacts and
+/// cargo-play's own state.
+pub fn copy_project<T: AsRef<Path>, U: AsRef<Path>>(from: T, to: U) -> Result<(), CargoPlayError> {
+    let to = to.as_ref();
+
+    if to.is_dir() {
+        return Err(CargoPlayError::PathExistError(to.to_path_buf()));
+    }
+
+    debug!("Copying {:?} => {:?}", from.as_ref(), to);
+    copy_dir(from.as_ref(), to, &["target", SOURCE_INDEX, COPIED_SOURCES])?;
+    println!("Generated project at {}", to.canonicalize()?.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cargo: &Command) -> Vec<String> {
+        cargo
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_main_fn_wrapper() {
+        let source = "fn unit() {}\nfn empty() -> () {}\nfn answer() -> u32 { 42 }\nfn add(a: u32) -> u32 { a }\n";
+
+        assert_eq!(
+            main_fn_wrapper(source, "unit").unwrap(),
+            "\nfn main() {\n    unit();\n}\n"
+        );
+        assert_eq!(
+            main_fn_wrapper(source, "empty").unwrap(),
+            "\nfn main() {\n    empty();\n}\n"
+        );
+        assert_eq!(
+            main_fn_wrapper(source, "answer").unwrap(),
+            "\nfn main() {\n    println!(\"{:?}\", answer());\n}\n"
+        );
+        assert!(main_fn_wrapper(source, "add").is_err());
+        assert!(main_fn_wrapper(source, "missing").is_err());
+        assert!(main_fn_wrapper("fn main() {}\nfn unit() {}", "unit").is_err());
+    }
+
+    #[test]
+    fn test_search_command() {
+        assert_eq!(
+            args(&search_command(None, None, "serde")),
+            vec!["search", "--limit", "1", "serde"]
+        );
+        assert_eq!(
+            args(&search_command(
+                Some("stable"),
+                Some("https://mirror.example.com/index"),
+                "serde"
+            )),
+            vec![
+                "+stable",
+                "search",
+                "--limit",
+                "1",
+                "--index",
+                "https://mirror.example.com/index",
+                "serde"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_result() {
+        let output = "async-std = \"1.12.0\"    # Async version of the Rust standard library\n\
+                      ... and 1234 crates more (use --limit N to see more)\n";
+
+        assert_eq!(
+            search_result(output, "async_std"),
+            Some(("async-std".into(), "1.12.0".into()))
+        );
+        assert_eq!(
+            search_result(output, "async-std"),
+            Some(("async-std".into(), "1.12.0".into()))
+        );
+        assert_eq!(search_result(output, "async"), None);
+        assert_eq!(search_result("", "serde"), None);
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let vars = parse_env_file(
+            "# endpoint\nAPI_URL = http://localhost:8080/?a=b\n\nTOKEN=\"secret\"\nEMPTY=\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vars,
+            vec![
+                ("API_URL".into(), "http://localhost:8080/?a=b".into()),
+                ("TOKEN".into(), "secret".into()),
+                ("EMPTY".into(), "".into()),
+            ]
+        );
+
+        let error = parse_env_file("A=1\n\nnot a variable\n").unwrap_err();
+        assert!(error.to_string().contains("line 3"));
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn test_parse_non_utf8_input() {
+        let path =
+            std::env::temp_dir().join(format!("cargo-play-latin1-{}.rs", rand::random::<u64>()));
+        // "// café" encoded as Latin-1
+        std::fs::write(&path, b"// caf\xe9\nfn main() {}\n").unwrap();
+
+        let result = parse_inputs(std::slice::from_ref(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(CargoPlayError::NonUtf8Source(source)) => assert_eq!(source, path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        use std::time::{Duration, SystemTime};
+
+        let dir = std::env::temp_dir().join(format!("cargo-play-fresh-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let touch = |name: &str, age: u64| {
+            let path = dir.join(name);
+            let file = File::create(&path).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(age))
+                .unwrap();
+            path
+        };
+
+        let binary = touch("binary", 60);
+        let old = touch("old.rs", 120);
+        let new = touch("new.rs", 0);
+
+        assert!(is_fresh(&binary, std::slice::from_ref(&old)));
+        assert!(!is_fresh(&binary, &[new, old.clone()]));
+        assert!(!is_fresh(&binary, &[dir.join("missing.rs")]));
+        assert!(!is_fresh(&dir.join("missing"), &[old]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_project() {
+        let dir = std::env::temp_dir().join(format!("cargo-play-save-{}", rand::random::<u64>()));
+        let (project, save) = (dir.join("project"), dir.join("saved"));
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::create_dir_all(project.join("target").join("debug")).unwrap();
+        std::fs::write(project.join("Cargo.toml"), "[package]\n").unwrap();
+        std::fs::write(project.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(project.join("target").join("debug").join("binary"), "").unwrap();
+        std::fs::write(project.join(SOURCE_INDEX), "/snippet.rs\n").unwrap();
+        std::fs::write(project.join(COPIED_SOURCES), "").unwrap();
+
+        copy_project(&project, &save).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(save.join("Cargo.toml")).unwrap(),
+            "[package]\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(save.join("src").join("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(!save.join("target").exists());
+        assert!(!save.join(SOURCE_INDEX).exists());
+        assert!(!save.join(COPIED_SOURCES).exists());
+
+        assert!(matches!(
+            copy_project(&project, &save),
+            Err(CargoPlayError::PathExistError(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_tests_only() {
+        assert!(is_tests_only("#[test]\nfn works() { assert!(true); }"));
+        assert!(is_tests_only(
+            "fn answer() -> u32 { 42 }\n#[cfg(test)]\nmod tests {\n    #[test]\n    fn works() {}\n}"
+        ));
+        assert!(!is_tests_only(
+            "fn main() {}\n#[test]\nfn works() { assert!(true); }"
+        ));
+        assert!(!is_tests_only("fn answer() -> u32 { 42 }"));
+        assert!(!is_tests_only("#[test] fn"));
+    }
+
+    #[test]
+    fn test_run_as_test_wrapper() {
+        assert_eq!(
+            run_as_test_wrapper("let x = 1;\nassert_eq!(x, 1);"),
+            "#[test]\nfn run_as_test() {\nlet x = 1;\nassert_eq!(x, 1);\n}\n"
+        );
+        assert_eq!(
+            run_as_test_wrapper("fn main() {}"),
+            "fn main() {}\n#[test]\nfn run_as_test() {\n    main()\n}\n"
+        );
+        assert!(
+            run_as_test_wrapper("fn main() -> Result<(), String> { Ok(()) }")
+                .contains("fn run_as_test() -> Result < () , String > {")
+        );
+    }
+
+    #[test]
+    fn test_allocator() {
+        assert!(defines_global_allocator(
+            "use std::alloc::System;\n#[global_allocator]\nstatic A: System = System;\nfn main() {}"
+        ));
+        assert!(!defines_global_allocator("fn main() {}"));
+
+        for (allocator, dependency, path) in &[
+            (
+                Allocator::Jemalloc,
+                Some("tikv-jemallocator"),
+                "tikv_jemallocator::Jemalloc",
+            ),
+            (Allocator::Mimalloc, Some("mimalloc"), "mimalloc::MiMalloc"),
+            (Allocator::System, None, "std::alloc::System"),
+        ] {
+            assert_eq!(
+                allocator
+                    .dependency()
+                    .map(|header| header.split(' ').next().unwrap()),
+                *dependency
+            );
+
+            let source = format!("fn main() {{}}\n{}", allocator_static(*allocator));
+            assert!(source.contains(&format!("static GLOBAL: {0} = {0};", path)));
+            assert!(defines_global_allocator(&source));
+        }
+    }
+
+    #[test]
+    fn test_add_main_result() {
+        let source = "use std::fs;\n\nfn main() {\n    let text = fs::read_to_string(\"a.txt\")?;\n    println!(\"{}\", text);\n}\n";
+        assert_eq!(
+            add_main_result(source).unwrap(),
+            "use std::fs;\n\nfn main() -> Result<(), Box<dyn std::error::Error>> {\n    let text = fs::read_to_string(\"a.txt\")?;\n    println!(\"{}\", text);\n    Ok(())\n}\n"
+        );
+
+        // a tail expression is turned into a statement
+        assert_eq!(
+            add_main_result("fn main() { let n: u8 = \"1\".parse()?; drop(n) }").unwrap(),
+            "fn main() -> Result<(), Box<dyn std::error::Error>> { let n: u8 = \"1\".parse()?; drop(n);\n    Ok(())\n}"
+        );
+
+        // declared return types are left alone
+        assert!(add_main_result(
+            "fn main() -> Result<(), std::num::ParseIntError> {\n    let n: u8 = \"1\".parse()?;\n    Ok(())\n}"
+        )
+        .is_none());
+        assert!(add_main_result("fn main() {\n    println!(\"hi\");\n}").is_none());
+        assert!(add_main_result("fn main() {").is_none());
+
+        // `?` in macro arguments counts
+        assert!(add_main_result("fn main() { println!(\"{}\", \"1\".parse::<u8>()?); }").is_some());
+    }
+
+    #[test]
+    fn test_add_main_result_nested() {
+        // closures, async blocks and nested functions return on their own
+        let source = "fn main() {\n    let parse = |s: &str| -> Option<i32> { Some(s.parse::<i32>().ok()? + 1) };\n    if parse(\"1\").is_some() {\n        return;\n    }\n}\n";
+        assert!(add_main_result(source).is_none());
+        assert!(add_main_result(
+            "fn main() {\n    let f = async { Some(1)? };\n    fn half(n: &str) -> Option<u8> { Some(n.parse::<u8>().ok()? / 2) }\n}\n"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_add_main_result_bare_return() {
+        let source = "fn main() {\n    let n: u8 = \"1\".parse()?;\n    if n == 1 {\n        return;\n    }\n    let check = || { return; };\n    check();\n}\n";
+        assert_eq!(
+            add_main_result(source).unwrap(),
+            "fn main() -> Result<(), Box<dyn std::error::Error>> {\n    let n: u8 = \"1\".parse()?;\n    if n == 1 {\n        return Ok(());\n    }\n    let check = || { return; };\n    check();\n    Ok(())\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_container_command() {
+        let project = Path::new("/tmp/cargo-play.abc");
+        let command = container_command("podman", "rust:1", project, &["RUSTFLAGS".into()]);
+
+        assert_eq!(command.get_program(), "podman");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "run",
+                "--rm",
+                "-i",
+                "-v",
+                "/tmp/cargo-play.abc:/tmp/cargo-play.abc",
+                "-w",
+                "/tmp/cargo-play.abc",
+                "-e",
+                "RUSTFLAGS",
+                "rust:1",
+                "cargo",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rustc_version() {
+        assert_eq!(
+            rustc_version("rustc 1.75.0 (82e1608df 2023-12-21)\n"),
+            Some((1, 75))
+        );
+        assert_eq!(
+            rustc_version("rustc 1.80.0-nightly (b1ec1bd65 2024-05-18)"),
+            Some((1, 80))
+        );
+        assert_eq!(rustc_version(""), None);
+    }
+
+    #[test]
+    fn test_parse_rust_version() {
+        assert_eq!(parse_rust_version("1.70"), Some((1, 70, 0)));
+        assert_eq!(parse_rust_version("1.70.1"), Some((1, 70, 1)));
+        assert_eq!(parse_rust_version("1.80.0-nightly"), Some((1, 80, 0)));
+        assert_eq!(parse_rust_version("1"), None);
+        assert_eq!(parse_rust_version("1.70.0.1"), None);
+        assert_eq!(parse_rust_version("latest"), None);
+
+        // compared numerically, not as strings
+        assert!(parse_rust_version("1.9") < parse_rust_version("1.70"));
+        assert!(parse_rust_version("1.70") < parse_rust_version("1.70.1"));
+        assert!(
+            rustc_full_version("rustc 1.69.0 (84c898d65 2023-04-16)") < parse_rust_version("1.70")
+        );
+    }
+
+    #[test]
+    fn test_add_async_runtime() {
+        let source = "use std::io;\n\nasync fn main() {\n    println!(\"hi\");\n}\n";
+
+        assert_eq!(
+            add_async_runtime(source, &["tokio".into()]).unwrap(),
+            Some(String::from(
+                "use std::io;\n\n#[tokio::main]\nasync fn main() {\n    println!(\"hi\");\n}\n"
+            ))
+        );
+        assert!(add_async_runtime(source, &["async-std".into()])
+            .unwrap()
+            .unwrap()
+            .contains("#[async_std::main]\nasync fn main"));
+
+        match add_async_runtime(source, &["serde".into()]) {
+            Err(CargoPlayError::NoAsyncRuntime) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // already wired, or not async at all
+        let wired = "#[tokio::main]\nasync fn main() {}\n";
+        assert_eq!(add_async_runtime(wired, &[]).unwrap(), None);
+
+        let documented = "/// Greets.\n#[allow(unused)]\npub async fn main() {}\n";
+        assert_eq!(
+            add_async_runtime(documented, &["tokio".into()]).unwrap(),
+            Some(String::from(
+                "/// Greets.\n#[allow(unused)]\n#[tokio::main]\npub async fn main() {}\n"
+            ))
+        );
+
+        let helper = "async fn main_loop() {}\n\nasync fn main() {\n    main_loop().await;\n}\n";
+        assert_eq!(
+            add_async_runtime(helper, &["tokio".into()]).unwrap(),
+            Some(String::from(
+                "async fn main_loop() {}\n\n#[tokio::main]\nasync fn main() {\n    main_loop().await;\n}\n"
+            ))
+        );
+        assert_eq!(add_async_runtime("fn main() {}", &[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_concat_sources() {
+        let files = vec![
+            String::from("#!/bin/true\n//# serde = \"1\"\n\nfn greet() {}\n//# kept\n"),
+            String::from("//# log = \"0.4\"\nfn main() {\n    greet();\n}\n"),
+        ];
+
+        assert_eq!(
+            concat_sources(&files),
+            "\nfn greet() {}\n//# kept\n\nfn main() {\n    greet();\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_debugger_command() {
+        let binary = Path::new("/tmp/project/target/debug/play");
+        let opt = Opt {
+            args: vec!["--flag".into()],
+            env: vec!["RUST_BACKTRACE=1".parse().unwrap()],
+            ..Default::default()
+        };
+        let gdb = debugger_command(&opt, "gdb", "rust-gdb", "--args", binary).unwrap();
+        assert_eq!(gdb.get_program(), "rust-gdb");
+        assert_eq!(args(&gdb), ["--args", binary.to_str().unwrap(), "--flag"]);
+        assert_eq!(
+            gdb.get_envs().collect::<Vec<_>>(),
+            [("RUST_BACKTRACE".as_ref(), Some("1".as_ref()))]
+        );
+
+        let opt = Opt {
+            sandbox: Some("firejail --quiet".into()),
+            arg0: Some("it's".into()),
+            ..Default::default()
+        };
+        let gdb = debugger_command(&opt, "gdb", "rust-gdb", "--args", binary).unwrap();
+        assert_eq!(gdb.get_program(), "firejail");
+        assert_eq!(
+            args(&gdb),
+            [
+                "--quiet",
+                "rust-gdb",
+                "-iex",
+                "set exec-wrapper exec -a 'it'\\''s'",
+                "--args",
+                binary.to_str().unwrap(),
+            ]
+        );
+
+        let opt = Opt {
+            arg0: Some("play".into()),
+            ..Default::default()
+        };
+        let lldb = debugger_command(&opt, "lldb", "lldb", "--", binary).unwrap();
+        assert_eq!(lldb.get_program(), "lldb");
+        assert_eq!(
+            args(&lldb),
+            [
+                "-O",
+                "settings set target.arg0 play",
+                "--",
+                binary.to_str().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batch_summary() {
+        let results = [
+            ("hello.rs", BatchOutcome::from(0)),
+            ("exit.rs", BatchOutcome::from(3)),
+            ("member", BatchOutcome::Failed(None)),
+            ("later.rs", BatchOutcome::Skipped),
+        ];
+
+        assert_eq!(
+            batch_summary(&results),
+            [
+                "    ok       hello.rs",
+                "    failed   exit.rs (exit code 3)",
+                "    failed   member",
+                "    skipped  later.rs",
+                "1 succeeded, 2 failed, 1 skipped",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_member_name() {
+        assert_eq!(
+            member_name(Path::new("fixtures/infer-failure.rs")),
+            "infer-failure"
+        );
+        assert_eq!(member_name(Path::new("2d.v2.rs")), "_2d_v2");
+        assert_eq!(member_name(Path::new("Hello.rs")), "hello");
+    }
+
+    #[test]
+    fn test_member_outcomes() {
+        let dir = Path::new("/tmp/ws");
+        let members = ["app".to_string(), "broken".to_string(), "user".to_string()];
+        let messages = r#"
+{"reason":"compiler-artifact","manifest_path":"/tmp/ws/app/Cargo.toml"}
+{"reason":"compiler-message","manifest_path":"/tmp/ws/broken/Cargo.toml","message":{"level":"warning","rendered":""}}
+{"reason":"compiler-message","manifest_path":"/tmp/ws/broken/Cargo.toml","message":{"level":"error","rendered":""}}
+{"reason":"build-finished","success":false}
+"#;
+
+        assert_eq!(
+            member_outcomes(dir, &members, messages.as_bytes()).unwrap(),
+            [
+                BatchOutcome::Succeeded,
+                BatchOutcome::Failed(None),
+                BatchOutcome::Skipped
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolution_flags() {
+        let opt = Opt {
+            target: Some("wasm32-wasi".into()),
+            offline: true,
+            release: true,
+            bin: Some("other".into()),
+            ..Default::default()
+        };
+        let mut tree = project_command(&opt, Path::new("/tmp/project"), &["tree"]);
+        resolution_flags(&opt, &mut tree);
+
+        // build-only flags are left to `base_command`
+        assert_eq!(
+            args(&tree),
+            [
+                "tree",
+                "--manifest-path",
+                "/tmp/project/Cargo.toml",
+                "--target",
+                "wasm32-wasi",
+                "--offline"
+            ]
+        );
+        assert_eq!(
+            args(&build_command(&opt, Path::new("/tmp/project")))[3..],
+            [
+                "--release",
+                "--target",
+                "wasm32-wasi",
+                "--offline",
+                "--bin",
+                "other"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cargo_command_without_args() {
+        let opt = Opt::default();
+        let cargo = cargo_command(&opt, Path::new("/tmp/project"));
+
+        assert_eq!(
+            args(&cargo),
+            vec!["run", "--manifest-path", "/tmp/project/Cargo.toml"]
+        );
+    }
+
+    #[test]
+    fn test_cargo_command_with_args() {
+        let opt = Opt {
+            args: vec!["--flag".into(), "value".into()],
+            ..Default::default()
+        };
+        let cargo = cargo_command(&opt, Path::new("/tmp/project"));
+
+        assert_eq!(args(&cargo)[3..], ["--", "--flag", "value"]);
+    }
+
+    #[test]
+    fn test_cargo_command_env() {
+        let opt = Opt {
+            env: vec![
+                "RUST_BACKTRACE=1".parse().unwrap(),
+                "GREETING=hello world".parse().unwrap(),
+            ],
+            extra_rustflags: Some("-C target-cpu=native".into()),
+            ..Default::default()
+        };
+        let env = |cargo: Command| {
+            cargo
+                .get_envs()
+                .map(|(name, value)| {
+                    (
+                        name.to_string_lossy().into_owned(),
+                        value.map(|value| value.to_string_lossy().into_owned()),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for cargo in [
+            cargo_command(&opt, Path::new("/tmp/project")),
+            build_command(&opt, Path::new("/tmp/project")),
+        ] {
+            let env = env(cargo);
+            assert!(env.contains(&("RUST_BACKTRACE".into(), Some("1".into()))));
+            assert!(env.contains(&("GREETING".into(), Some("hello world".into()))));
+            assert!(env.iter().any(|(name, value)| name == "RUSTFLAGS"
+                && value
+                    .as_deref()
+                    .is_some_and(|flags| flags.ends_with("-C target-cpu=native"))));
+        }
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let opt = Opt {
+            env_file: Some("fixtures/play.env".into()),
+            env: vec!["PLAY_ENV=from flag".parse().unwrap()],
+            ..Default::default()
+        };
+
+        // set in that order, the flag wins
+        assert_eq!(
+            env_vars(&opt).unwrap(),
+            vec![
+                ("PLAY_ENV".into(), "from env file".into()),
+                ("PLAY_ENV".into(), "from flag".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cargo_command_offline() {
+        let offline = Opt {
+            offline: true,
+            args: vec!["--flag".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            args(&cargo_command(&offline, Path::new("/tmp/project")))[3..],
+            ["--offline", "--", "--flag"]
+        );
+
+        let locked = Opt {
+            locked: true,
+            cargo_option: Some("--jobs 2".into()),
+            ..offline
+        };
+        assert_eq!(
+            args(&build_command(&locked, Path::new("/tmp/project")))[3..],
+            ["--jobs", "2", "--offline", "--locked"]
+        );
+    }
+
+    #[test]
+    fn test_cargo_command_quiet() {
+        let opt = Opt {
+            quiet: true,
+            quiet_on_success: true,
+            args: vec!["--flag".into()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            args(&cargo_command(&opt, Path::new("/tmp/project"))),
+            vec![
+                "run",
+                "--manifest-path",
+                "/tmp/project/Cargo.toml",
+                "--quiet",
+                "--",
+                "--flag"
+            ]
+        );
+        assert_eq!(
+            args(&build_command(&opt, Path::new("/tmp/project")))[3..],
+            ["--quiet"]
+        );
+    }
+
+    #[test]
+    fn test_cargo_command_check_and_clippy() {
+        let check = Opt {
+            check: true,
+            cargo_option: Some("--all-targets".into()),
+            args: vec!["--flag".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            args(&cargo_command(&check, Path::new("/tmp/project"))),
+            vec![
+                "check",
+                "--manifest-path",
+                "/tmp/project/Cargo.toml",
+                "--all-targets"
+            ]
+        );
+        assert_eq!(
+            args(&build_command(&check, Path::new("/tmp/project")))[0],
+            "check"
+        );
+
+        let clippy = Opt {
+            check: false,
+            clippy: true,
+            args: vec!["-D".into(), "warnings".into()],
+            ..check
+        };
+        assert_eq!(
+            args(&cargo_command(&clippy, Path::new("/tmp/project"))),
+            vec![
+                "clippy",
+                "--manifest-path",
+                "/tmp/project/Cargo.toml",
+                "--all-targets",
+                "--",
+                "-D",
+                "warnings"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_target_dir() {
+        let project = Path::new("/tmp/project");
+        let target_dir = |cargo: &Command| {
+            cargo
+                .get_envs()
+                .find(|(name, _)| *name == "CARGO_TARGET_DIR")
+                .and_then(|(_, value)| value.map(PathBuf::from))
+        };
+        assert_eq!(target_dir(&cargo_command(&Opt::default(), project)), None);
+
+        let opt = Opt {
+            target_dir: Some("/tmp/shared".into()),
+            release: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            target_dir(&cargo_command(&opt, project)),
+            Some(PathBuf::from("/tmp/shared"))
+        );
+        assert_eq!(
+            binary_path(&opt, project, "snippet"),
+            PathBuf::from(format!(
+                "/tmp/shared/release/snippet{}",
+                env::consts::EXE_SUFFIX
+            ))
+        );
+    }
+
+    #[test]
+    fn test_cross_target() {
+        let project = Path::new("/tmp/project");
+        let opt = Opt {
+            target: Some("aarch64-unknown-linux-gnu".into()),
+            target_dir: Some("/tmp/shared".into()),
+            release: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            args(&cargo_command(&opt, project))[3..],
+            ["--release", "--target", "aarch64-unknown-linux-gnu"]
+        );
+        assert_eq!(
+            binary_path(&opt, project, "snippet"),
+            PathBuf::from("/tmp/shared/aarch64-unknown-linux-gnu/release/snippet")
+        );
+
+        let windows = Opt {
+            target: Some("x86_64-pc-windows-gnu".into()),
+            release: false,
+            ..opt
+        };
+        assert_eq!(
+            binary_path(&windows, project, "snippet"),
+            PathBuf::from("/tmp/shared/x86_64-pc-windows-gnu/debug/snippet.exe")
+        );
+    }
+
+    #[test]
+    fn test_dated_toolchain() {
+        let parse = |args: &[&str]| match Opt::parse(args.iter().map(|s| s.to_string()).collect()) {
+            Ok(crate::opt::Invocation::Play(opt)) => *opt,
+            _ => panic!("failed to parse {:?}", args),
+        };
+
+        let opt = parse(&["cargo", "play", "+nightly-2024-01-15", "fixtures/hello.rs"]);
+        assert_eq!(opt.toolchain.as_deref(), Some("nightly-2024-01-15"));
+        assert_eq!(
+            args(&cargo_command(&opt, Path::new("/tmp/project")))[0],
+            "+nightly-2024-01-15"
+        );
+
+        // the flag isn't clobbered when there is no `+toolchain` argument
+        let flag = parse(&[
+            "cargo-play",
+            "--toolchain",
+            "nightly-2024-01-15",
+            "fixtures/hello.rs",
+        ]);
+        assert_eq!(flag.toolchain.as_deref(), Some("nightly-2024-01-15"));
+
+        // program arguments are left alone
+        let program = parse(&["cargo-play", "fixtures/hello.rs", "--", "+1"]);
+        assert_eq!(program.toolchain, None);
+        assert_eq!(program.args, ["+1"]);
+
+        assert_ne!(opt.src_hash(), program.src_hash());
+    }
 }
@@ -0,0 +1,89 @@
+//! Starter snippets for `cargo play new --template <name>`.
+
+/// Names of the available templates, in the order they are listed in `--help`.
+pub const NAMES: &[&str] = &["hello", "async", "clap", "bench"];
+
+const HELLO: &str = r#"fn main() {
+    println!("Hello, world!");
+}
+"#;
+
+const ASYNC: &str = r#"//# tokio = { version = "1", features = ["full"] }
+
+#[tokio::main]
+async fn main() {
+    let task = tokio::spawn(async { 21 * 2 });
+
+    println!("answer: {}", task.await.unwrap());
+}
+"#;
+
+const CLAP: &str = r#"//# clap = { version = "4", features = ["derive"] }
+
+use clap::Parser;
+
+/// Describe the tool here
+#[derive(Debug, Parser)]
+struct Args {
+    /// Who to greet
+    #[arg(short, long, default_value = "world")]
+    name: String,
+
+    /// Number of greetings
+    #[arg(short, long, default_value_t = 1)]
+    count: u32,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    for _ in 0..args.count {
+        println!("Hello, {}!", args.name);
+    }
+}
+"#;
+
+const BENCH: &str = r#"//# criterion = "0.3"
+
+// run with `cargo play --release <file>`
+use criterion::{black_box, Criterion};
+
+fn fibonacci(n: u64) -> u64 {
+    match n {
+        0 | 1 => 1,
+        n => fibonacci(n - 1) + fibonacci(n - 2),
+    }
+}
+
+fn main() {
+    let mut criterion = Criterion::default().configure_from_args();
+
+    criterion.bench_function("fib 20", |b| b.iter(|| fibonacci(black_box(20))));
+    criterion.final_summary();
+}
+"#;
+
+/// Content of the template with the given name.
+pub fn template(name: &str) -> Option<&'static str> {
+    match name {
+        "hello" => Some(HELLO),
+        "async" => Some(ASYNC),
+        "clap" => Some(CLAP),
+        "bench" => Some(BENCH),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates_parse() {
+        for name in NAMES {
+            let content = template(name).unwrap();
+            assert!(syn::parse_file(content).is_ok(), "{}", name);
+        }
+        assert!(template("unknown").is_none());
+    }
+}
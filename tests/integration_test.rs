@@ -1,6 +1,9 @@
 use cargo_play::opt::Opt;
+use cargo_play::steps::binary_path;
+use cargo_play::Playground;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use std::collections::HashSet;
 use std::env;
 use std::ffi::OsStr;
 use std::io::Result;
@@ -58,6 +61,28 @@ impl TestRuntime {
             .output()
             .map(From::from)
     }
+
+    fn run_with_stdin<
+        I: IntoIterator<Item = S> + std::fmt::Debug,
+        S: AsRef<OsStr> + std::fmt::Debug,
+    >(
+        &self,
+        args: I,
+        input: &str,
+    ) -> std::io::Result<StringOutput> {
+        use std::io::Write;
+
+        let mut play = std::process::Command::new(cargo_play_binary_path())
+            .env("TMP", &self.scratch)
+            .env("TMPDIR", &self.scratch)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        play.stdin.take().unwrap().write_all(input.as_bytes())?;
+        play.wait_with_output().map(From::from)
+    }
 }
 
 impl Drop for TestRuntime {
@@ -97,7 +122,7 @@ impl From<std::process::Output> for StringOutput {
 #[test]
 fn basic() -> Result<()> {
     let rt = TestRuntime::new()?;
-    let output = rt.run(&["fixtures/hello.rs"])?;
+    let output = rt.run(["fixtures/hello.rs"])?;
 
     assert_eq!(output.status.code().unwrap(), 0);
     assert_eq!(output.stdout, "Hello World!\n");
@@ -117,13 +142,13 @@ fn clean() -> Result<()> {
     }
 
     println!("{:?}", path);
-    let _ = rt.run(&["fixtures/hello.rs"])?;
+    let _ = rt.run(["fixtures/hello.rs"])?;
     assert!(path.exists());
 
     std::fs::write(&canary, "I_AM_CANARY")?;
 
     assert!(canary.exists());
-    let _ = rt.run(&["--clean", "fixtures/hello.rs"])?;
+    let _ = rt.run(["--clean", "fixtures/hello.rs"])?;
     assert!(!canary.exists());
 
     Ok(())
@@ -133,15 +158,15 @@ fn clean() -> Result<()> {
 fn edition() -> Result<()> {
     let rt = TestRuntime::new()?;
 
-    // default edition is 2018
-    let output = rt.run(&["fixtures/edition.rs"])?;
+    // default edition is 2021
+    let output = rt.run(["fixtures/edition.rs"])?;
     assert_ne!(output.status.code().unwrap(), 0);
 
-    let output = rt.run(&["--edition", "2018", "fixtures/edition.rs"])?;
+    let output = rt.run(["--edition", "2018", "fixtures/edition.rs"])?;
     assert_ne!(output.status.code().unwrap(), 0);
 
     // it should pass in 2015
-    let output = rt.run(&["--edition", "2015", "fixtures/edition.rs"])?;
+    let output = rt.run(["--edition", "2015", "fixtures/edition.rs"])?;
     assert_eq!(output.status.code().unwrap(), 0);
 
     Ok(())
@@ -154,7 +179,7 @@ fn debug_mode() -> Result<()> {
     let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
     let path = rt.temp_dir(opt.temp_dirname());
 
-    let _ = rt.run(&["fixtures/hello.rs"])?;
+    let _ = rt.run(["fixtures/hello.rs"])?;
     assert!(path.join("target").join("debug").exists());
     assert!(!path.join("target").join("release").exists());
 
@@ -168,7 +193,7 @@ fn release_mode() -> Result<()> {
     let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
     let path = rt.temp_dir(opt.temp_dirname());
 
-    let _ = rt.run(&["--release", "fixtures/hello.rs"])?;
+    let _ = rt.run(["--release", "fixtures/hello.rs"])?;
     assert!(!path.join("target").join("debug").exists());
     assert!(path.join("target").join("release").exists());
 
@@ -182,7 +207,7 @@ fn cargo_option() -> Result<()> {
     let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
     let path = rt.temp_dir(opt.temp_dirname());
 
-    let _ = rt.run(&["--cargo-option=--release", "fixtures/hello.rs"])?;
+    let _ = rt.run(["--cargo-option=--release", "fixtures/hello.rs"])?;
 
     assert!(!path.join("target").join("debug").exists());
     assert!(path.join("target").join("release").exists());
@@ -194,7 +219,7 @@ fn cargo_option() -> Result<()> {
 fn program_args() -> Result<()> {
     let rt = TestRuntime::new()?;
 
-    let output = rt.run(&["fixtures/args.rs", "--", "test"])?;
+    let output = rt.run(["fixtures/args.rs", "--", "test"])?;
     assert_eq!(output.stdout, "test\n");
 
     Ok(())
@@ -204,7 +229,7 @@ fn program_args() -> Result<()> {
 fn external_crate() -> Result<()> {
     let rt = TestRuntime::new()?;
 
-    let output = rt.run(&["fixtures/bitflags.rs"])?;
+    let output = rt.run(["fixtures/bitflags.rs"])?;
     assert_eq!(output.status.code().unwrap(), 0);
 
     Ok(())
@@ -213,7 +238,7 @@ fn external_crate() -> Result<()> {
 #[test]
 fn simple_infer() -> Result<()> {
     let rt = TestRuntime::new()?;
-    let output = rt.run(&["--infer", "fixtures/infer.rs"])?;
+    let output = rt.run(["--infer", "fixtures/infer.rs"])?;
     assert_eq!(output.status.code().unwrap(), 0);
 
     Ok(())
@@ -222,7 +247,7 @@ fn simple_infer() -> Result<()> {
 #[test]
 fn infer_failure() -> Result<()> {
     let rt = TestRuntime::new()?;
-    let output = rt.run(&["--infer", "fixtures/infer-failure.rs"])?;
+    let output = rt.run(["--infer", "fixtures/infer-failure.rs"])?;
     assert_ne!(output.status.code().unwrap(), 0);
 
     Ok(())
@@ -231,7 +256,7 @@ fn infer_failure() -> Result<()> {
 #[test]
 fn infer_override() -> Result<()> {
     let rt = TestRuntime::new()?;
-    let output = rt.run(&["--infer", "fixtures/infer-override.rs"])?;
+    let output = rt.run(["--infer", "fixtures/infer-override.rs"])?;
     assert_eq!(output.status.code().unwrap(), 0);
 
     Ok(())
@@ -241,8 +266,1499 @@ fn infer_override() -> Result<()> {
 #[test]
 fn dtoa_test() -> Result<()> {
     let rt = TestRuntime::new()?;
-    let output = rt.run(&["fixtures/dtoa.rs"])?;
+    let output = rt.run(["fixtures/dtoa.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn emit_manifest() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt::with_files(vec!["fixtures/bitflags.rs"]);
+    let manifest = rt.temp_dir("Cargo.toml");
+
+    let output = rt.run([
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/bitflags.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    let content = std::fs::read_to_string(&manifest)?;
+    assert!(content.contains("[dependencies]"));
+    assert!(content.contains(r#"bitflags = "1.1.0""#));
+
+    // only the manifest is written, no project is set up
+    assert!(!rt.temp_dir(opt.temp_dirname()).exists());
+
+    Ok(())
+}
+
+#[test]
+fn sandbox() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    // `env` stands in for a real isolation wrapper such as firejail
+    let output = rt.run(["--sandbox", "env PLAY_ENV=sandboxed", "fixtures/env.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "sandboxed\n");
+
+    Ok(())
+}
+
+#[test]
+fn library_with_test_files() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run([
+        "--lib",
+        "fixtures/library/answer.rs",
+        "--test-file",
+        "fixtures/library/integration.rs",
+    ])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stdout.contains("test answer_is_42 ... ok"));
+
+    Ok(())
+}
+
+#[test]
+fn print_deps() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--print-deps", "fixtures/bitflags.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stdout.contains(r#"bitflags = "1."#));
+
+    Ok(())
+}
+
+#[test]
+fn edition_header() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["fixtures/edition-header.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    // the command line flag takes precedence over the header
+    let output = rt.run(["--edition", "2018", "fixtures/edition-header.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn from_project() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    // bitflags is only declared in the project's Cargo.toml
+    let output = rt.run(["fixtures/from-project.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+
+    let output = rt.run([
+        "--from-project",
+        "fixtures/project",
+        "fixtures/from-project.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn tests_only() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["fixtures/tests-only.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stdout.contains("test works ... ok"));
+
+    Ok(())
+}
+
+#[test]
+fn run_as_test() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--run-as-test", "fixtures/assertions.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stdout.contains("test run_as_test ... ok"));
+
+    let output = rt.run(["--run-as-test", "fixtures/assertions-fail.rs"])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stdout.contains("test run_as_test ... FAILED"));
+    assert!(output.stdout.contains("wrong answer"));
+
+    Ok(())
+}
+
+#[test]
+fn ensure_components() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    // already installed, so rustup has nothing to download
+    let output = rt.run(["--ensure-components", "rustc", "fixtures/hello.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+
+    let output = rt.run([
+        "--ensure-components",
+        "no-such-component",
+        "fixtures/hello.rs",
+    ])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("rustup component add"));
+
+    Ok(())
+}
+
+#[test]
+fn sidecar() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let manifest = rt.temp_dir("Cargo.toml");
+
+    let output = rt.run([
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/sidecar/main.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("dependency `bitflags`"));
+
+    // the header of the source wins over the sidecar
+    let content = std::fs::read_to_string(&manifest)?;
+    assert!(content.contains(r#"bitflags = "1.1.0""#));
+    assert!(content.contains("[dev-dependencies]"));
+    assert!(content.contains(r#"rand = "0.7""#));
+
+    Ok(())
+}
+
+#[test]
+fn output_format_json() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--output-format", "json", "fixtures/edition.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 101);
+    let report: serde_json::Value = serde_json::from_str(&output.stdout)?;
+    assert_eq!(report["success"], false);
+    assert_eq!(report["stage"], "build");
+    assert_eq!(report["exit_code"], 101);
+    assert_eq!(report["error"], "BuildFailed");
+
+    let output = rt.run(["--output-format", "json", "fixtures/exit-status.rs"])?;
+    let report: serde_json::Value = serde_json::from_str(output.stdout.lines().last().unwrap())?;
+    assert_eq!(report["stage"], "run");
+    assert_eq!(report["exit_code"], output.status.code().unwrap());
+    assert!(report["error"].is_null());
+
+    Ok(())
+}
+
+#[test]
+fn allocator() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--allocator", "system", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+
+    for (allocator, dependency, path) in &[
+        (
+            "jemalloc",
+            "tikv-jemallocator",
+            "tikv_jemallocator::Jemalloc",
+        ),
+        ("mimalloc", "mimalloc", "mimalloc::MiMalloc"),
+    ] {
+        let save = rt.temp_dir(allocator);
+        let output = rt.run([
+            "--allocator",
+            allocator,
+            "--save",
+            save.to_str().unwrap(),
+            "fixtures/hello.rs",
+        ])?;
+        assert_eq!(output.status.code().unwrap(), 0);
+
+        let manifest = std::fs::read_to_string(save.join("Cargo.toml"))?;
+        assert!(manifest.contains(dependency));
+        let main = std::fs::read_to_string(save.join("src").join("main.rs"))?;
+        assert!(main.contains(&format!("static GLOBAL: {0} = {0};", path)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn try_main() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["fixtures/try-main.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "answer: 42\n");
+
+    // a declared return type is kept, so the error is the user's own
+    let output = rt.run(["fixtures/try-main-result.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 1);
+    assert!(output.stderr.contains("Error: ParseIntError"));
+
+    Ok(())
+}
+
+#[test]
+fn pre_expand_macros() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--pre-expand-macros", "fixtures/derive-without-feature.rs"])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output
+        .stderr
+        .contains("`#[derive(Serialize)]` needs the `derive` feature of `serde`"));
+    assert!(!output.stderr.contains("Compiling"));
+
+    Ok(())
+}
+
+#[test]
+fn existing_project() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let project = rt.temp_dir("project");
+    std::fs::create_dir_all(project.join("src"))?;
+    std::fs::copy("fixtures/project/Cargo.toml", project.join("Cargo.toml"))?;
+    std::fs::copy(
+        "fixtures/project/src/main.rs",
+        project.join("src").join("main.rs"),
+    )?;
+
+    let output = rt.run([project.to_str().unwrap()])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello from project!\n");
+
+    // the project is built in place, without a generated manifest
+    assert!(project.join("target").exists());
+    assert!(!rt
+        .temp_dir(Opt::with_files(vec![&project]).temp_dirname())
+        .exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn pty() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["fixtures/isatty.rs"])?;
+    assert_eq!(output.stdout, "false\n");
+
+    let output = rt.run(["--pty", "fixtures/isatty.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "true\n");
+
+    Ok(())
+}
+
+#[test]
+fn multiple_sources() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["fixtures/multi/entry.rs", "fixtures/multi/hello.rs"])?;
+    assert_eq!(output.stdout, "Hello World\n");
+
+    let output = rt.run(["fixtures/subdirs/hello.rs", "fixtures/subdirs/world/mod.rs"])?;
+    assert_eq!(output.stdout, "Hello World\n");
+
+    // files above the first one can't be placed under src/
+    let output = rt.run(["fixtures/multi/entry.rs", "fixtures/hello.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("SourceOutsideProject"));
+    let project = rt.temp_dir(
+        Opt::with_files(vec!["fixtures/multi/entry.rs", "fixtures/hello.rs"]).temp_dirname(),
+    );
+    assert!(!project.join("hello.rs").exists());
+
+    Ok(())
+}
+
+#[test]
+fn include_str() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let save = rt.temp_dir("saved");
+
+    let output = rt.run(["fixtures/includes/src/include-str.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello from a file!\n");
+
+    // saved projects keep the included file at the same relative location
+    let output = rt.run([
+        "--save",
+        save.to_str().unwrap(),
+        "fixtures/includes/src/include-str.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(save.join("greeting.txt").is_file());
+
+    Ok(())
+}
+
+#[test]
+fn include() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["fixtures/includes/src/include.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello from include!\n");
+
+    Ok(())
+}
+
+#[test]
+fn include_bytes() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["fixtures/includes/src/include-bytes.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "19 bytes\n");
+
+    Ok(())
+}
+
+#[test]
+fn include_computed_path() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["fixtures/includes/src/include-computed.rs"])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output
+        .stderr
+        .contains("warning: the path given to `include_str!`"));
+
+    Ok(())
+}
+
+#[test]
+fn check_only_changed() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let entry = rt.temp_dir("entry.rs");
+    let hello = rt.temp_dir("hello.rs");
+    std::fs::copy("fixtures/multi/entry.rs", &entry)?;
+    std::fs::copy("fixtures/multi/hello.rs", &hello)?;
+    let args = [
+        "--check-only-changed",
+        entry.to_str().unwrap(),
+        hello.to_str().unwrap(),
+    ];
+    let opt = Opt {
+        check_only_changed: true,
+        ..Opt::with_files(vec![&entry, &hello])
+    };
+    let project = rt.temp_dir(opt.temp_dirname());
+    let modified = |file: &str| std::fs::metadata(project.join("src").join(file))?.modified();
+
+    let output = rt.run(args)?;
+    assert_eq!(output.stdout, "Hello World\n");
+    let (main, module) = (modified("main.rs")?, modified("hello.rs")?);
+
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    std::fs::write(
+        &entry,
+        "mod hello;\n\nfn main() {\n    println!(\"Bye {}\", hello::world());\n}\n",
+    )?;
+    let output = rt.run(args)?;
+    assert_eq!(output.stdout, "Bye World\n");
+    assert_ne!(modified("main.rs")?, main);
+    assert_eq!(modified("hello.rs")?, module);
+
+    // copies of sources that are no longer given are removed
+    let stale = project.join("src").join("stale.rs");
+    std::fs::write(&stale, "")?;
+    let mut state = std::fs::read_to_string(project.join(".cargo-play-copied"))?;
+    state.push_str(&format!("hash {}\n", stale.display()));
+    std::fs::write(project.join(".cargo-play-copied"), state)?;
+    let _ = rt.run(args)?;
+    assert!(!stale.exists());
+
+    Ok(())
+}
+
+#[test]
+fn multiple_bins() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--bin", "hello", "fixtures/bins.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "hello\n");
+
+    let output = rt.run(["--bin", "bye", "fixtures/bins.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "bye\n");
+
+    Ok(())
+}
+
+#[test]
+fn quiet_on_success() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--quiet-on-success", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert_eq!(output.stderr, "");
+
+    // diagnostics are still shown when the build fails
+    let output = rt.run(["--quiet-on-success", "fixtures/edition.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("error"));
+
+    Ok(())
+}
+
+#[test]
+fn concat() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run([
+        "--concat",
+        "fixtures/concat/greet.rs",
+        "fixtures/concat/main.rs",
+    ])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+
+    // the file included from a subdirectory is found next to the concatenated source
+    let output = rt.run([
+        "--concat",
+        "fixtures/concat/include.rs",
+        "fixtures/concat/greet.rs",
+        "fixtures/concat/words/word.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+
+    Ok(())
+}
+
+#[test]
+fn separate() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let sources = [
+        "fixtures/hello.rs",
+        "fixtures/exit-status.rs",
+        "fixtures/concat/main.rs",
+    ];
+
+    let output = rt.run(["--separate"].iter().chain(&sources))?;
+    assert_eq!(output.status.code().unwrap(), 3);
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(output.stderr.contains("exit-status.rs (exit code 3)"));
+    assert!(output.stderr.contains("1 succeeded, 1 failed, 1 skipped"));
+
+    // main.rs fails to build on its own, without greet.rs
+    let output = rt.run(["--separate", "--keep-going"].iter().chain(&sources))?;
+    assert_eq!(output.status.code().unwrap(), 3);
+    assert!(output.stderr.contains("1 succeeded, 2 failed, 0 skipped"));
+
+    Ok(())
+}
+
+#[test]
+fn workspace() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run([
+        "--workspace",
+        "fixtures/workspace/greeting.rs",
+        "fixtures/workspace/app.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("    ok       greeting"));
+    assert!(output.stderr.contains("    ok       app"));
+    // members are only built
+    assert!(output.stdout.is_empty());
+
+    let output = rt.run([
+        "--workspace",
+        "--keep-going",
+        "fixtures/workspace/broken.rs",
+        "fixtures/workspace/greeting.rs",
+        "fixtures/workspace/app.rs",
+    ])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("expected `u32`, found `&str`"));
+    assert!(output.stderr.contains("    failed   broken"));
+    assert!(output.stderr.contains("2 succeeded, 1 failed, 0 skipped"));
+
+    Ok(())
+}
+
+#[test]
+fn emit_asm() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--emit", "asm", "fixtures/hello.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    // the program is only built, not run
+    assert!(!output.stdout.contains("Hello World!"));
+
+    let files: Vec<&str> = output.stdout.lines().collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with(".s"));
+    assert!(Path::new(files[0]).exists());
+
+    Ok(())
+}
+
+#[test]
+fn emit_metadata() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--emit-metadata", "--lib", "fixtures/library/answer.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    let files: Vec<&str> = output.stdout.lines().collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with(".rmeta"));
+    assert!(Path::new(files[0])
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("libanswer-"));
+
+    Ok(())
+}
+
+#[test]
+fn main_fn() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--main-fn", "greet", "fixtures/main-fn.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello from greet!\n");
+
+    let output = rt.run(["--main-fn", "answer", "fixtures/main-fn.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "42\n");
+
+    Ok(())
+}
+
+#[test]
+fn expand_without_cargo_expand() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--expand", "fixtures/hello.rs"])?;
+
+    // cargo-expand is not expected to be installed in the test environment
+    if output.status.success() {
+        assert!(output.stdout.contains("fn main"));
+    } else {
+        assert!(output.stderr.contains("cargo-expand"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn env_file() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--env-file", "fixtures/play.env", "fixtures/env.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "from env file\n");
+
+    let output = rt.run([
+        "--env-file",
+        "fixtures/play.env",
+        "--env",
+        "PLAY_ENV=from flag",
+        "fixtures/env.rs",
+    ])?;
+    assert_eq!(output.stdout, "from flag\n");
+
+    let output = rt.run(["--env", "PLAY_ENV", "fixtures/env.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("expected KEY=VALUE"));
+
+    Ok(())
+}
+
+#[test]
+fn explain_error() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--explain-error", "fixtures/no-main.rs"])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("E0601"));
+    assert!(output.stderr.contains("hint: no `main` function was found"));
+
+    Ok(())
+}
+
+#[test]
+fn explain_error_with_prebuild() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    for flag in ["--wrap-errors", "--run-count=2", "--output-format=json"] {
+        let output = rt.run([flag, "--explain-error", "fixtures/no-main.rs"])?;
+        assert_ne!(output.status.code().unwrap(), 0);
+        assert!(output.stderr.contains("hint: no `main` function was found"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn explain_error_of_program() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--explain-error", "fixtures/program-error.rs"])?;
+
+    // only the build is explained, the program's own stderr is left alone
+    assert_eq!(output.status.code().unwrap(), 1);
+    assert!(output.stderr.contains("unresolved import `serde_jsn`"));
+    assert!(!output.stderr.contains("hint:"));
+
+    Ok(())
+}
+
+#[test]
+fn explain_edition_error() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run([
+        "--explain-error",
+        "--edition",
+        "2015",
+        "fixtures/async-fn.rs",
+    ])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output
+        .stderr
+        .contains("hint: this code needs a newer edition than 2015; try --edition 2021"));
+
+    let output = rt.run(["--explain-error", "fixtures/async-fn.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn cfg() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["fixtures/cfg.rs"])?;
+    assert_eq!(output.stdout, "mycfg disabled\n");
+
+    let output = rt.run(["--cfg", "mycfg", "fixtures/cfg.rs"])?;
+    assert_eq!(output.stdout, "mycfg enabled\n");
+
+    Ok(())
+}
+
+#[test]
+fn out_dir() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let out_dir = rt.temp_dir("out");
+
+    let output = rt.run([
+        "--out-dir",
+        out_dir.to_str().unwrap(),
+        "--bin",
+        "bye",
+        "fixtures/bins.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(out_dir
+        .join(format!("bye{}", std::env::consts::EXE_SUFFIX))
+        .is_file());
+
+    let output = rt.run([
+        "--out-dir",
+        out_dir.to_str().unwrap(),
+        "--lib",
+        "fixtures/library/answer.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(out_dir.join("libanswer.rlib").is_file());
+
+    Ok(())
+}
+
+#[test]
+fn manifest_include() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let manifest = rt.temp_dir("Cargo.toml");
+
+    let output = rt.run([
+        "--manifest-include",
+        "fixtures/include.toml",
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/bitflags.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("dependency `bitflags`"));
+
+    let content = std::fs::read_to_string(&manifest)?;
+    assert!(content.contains(r#"bitflags = "1.1.0""#));
+    assert!(content.contains("[dev-dependencies]"));
+    assert!(content.contains(r#"rand = "0.7""#));
+
+    Ok(())
+}
+
+#[test]
+fn log_file() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let manifest = rt.temp_dir("Cargo.toml");
+    let log = rt.temp_dir("play.log");
+
+    let output = rt.run([
+        "--log-file",
+        log.to_str().unwrap(),
+        "--manifest-include",
+        "fixtures/include.toml",
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/bitflags.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("warning: dependency `bitflags`"));
+
+    let content = std::fs::read_to_string(&log)?;
+    assert!(content.contains("warning: dependency `bitflags`"));
+
+    Ok(())
+}
+
+#[test]
+fn panic_abort() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let manifest = rt.temp_dir("Cargo.toml");
+
+    let output = rt.run([
+        "--panic",
+        "abort",
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/hello.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    let content = std::fs::read_to_string(&manifest)?;
+    assert!(content.contains("[profile.dev]\npanic = \"abort\""));
+    assert!(content.contains("[profile.release]\npanic = \"abort\""));
+
+    Ok(())
+}
+
+#[test]
+fn embed_version() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt::with_files(vec!["fixtures/embed-version.rs"]);
+
+    let output = rt.run(["--embed-version", "fixtures/embed-version.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(
+        output.stdout,
+        format!("{} {}\n", opt.src_hash(), env!("CARGO_PKG_VERSION"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn strip() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let out_dir = rt.temp_dir("out");
+
+    let output = rt.run([
+        "--release",
+        "--strip",
+        "--out-dir",
+        out_dir.to_str().unwrap(),
+        "fixtures/hello.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("Stripped"));
+
+    Ok(())
+}
+
+#[test]
+fn list_cache() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
+
+    let _ = rt.run(["fixtures/hello.rs"])?;
+    let output = rt.run(["list-cache"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    let hash = opt.src_hash();
+    let mut lines = output.stdout.lines();
+    assert!(lines.next().unwrap().starts_with(&hash));
+    assert!(lines.next().unwrap().ends_with("hello.rs"));
+
+    Ok(())
+}
+
+#[test]
+fn async_main_without_runtime() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["fixtures/async-main.rs"])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("NoAsyncRuntime"));
+
+    Ok(())
+}
+
+#[test]
+fn library_with_main() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run([
+        "--infer",
+        "--lib",
+        "fixtures/lib-bin/greeting.rs",
+        "--main",
+        "fixtures/lib-bin/main.rs",
+    ])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello, lib!\n");
+
+    let output = rt.run(["list-cache"])?;
+    assert!(output.stdout.contains("lib-bin/greeting.rs\n"));
+    assert!(output.stdout.contains("lib-bin/main.rs\n"));
+
+    Ok(())
+}
+
+#[test]
+fn shebang_flags() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    // how the kernel invokes the script, with the shebang flags in a single argument
+    let output = rt.run(["--release --quiet-on-success", "fixtures/shebang.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "release\n");
+    assert!(output.stderr.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn wrap_errors() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--wrap-errors", "--unknown-flag", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 64);
+
+    let output = rt.run(["--wrap-errors", "fixtures/no-main.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 65);
+
+    let output = rt.run(["--wrap-errors", "fixtures/exit-status.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 3);
+
+    let output = rt.run(["--wrap-errors", "fixtures/async-main.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 64);
+
+    Ok(())
+}
+
+#[test]
+fn record_manifest() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
+    let record = rt.temp_dir("last");
+
+    let output = rt.run([
+        &format!("--record-manifest={}", record.display()),
+        "fixtures/hello.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    let manifest = rt.temp_dir(opt.temp_dirname()).join("Cargo.toml");
+    assert_eq!(
+        std::fs::read_to_string(record)?,
+        format!("CARGO_PLAY_MANIFEST={}\n", manifest.display())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn trace_cargo() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--trace-cargo", "fixtures/bitflags.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stdout.contains("bitflags feature \"default\""));
+
+    Ok(())
+}
+
+#[test]
+fn run_count() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--run-count", "3", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n".repeat(3));
+    assert!(output.stderr.contains("3 runs in"));
+
+    // stops at the first failure unless --run-all is given
+    let output = rt.run(["--run-count", "3", "fixtures/exit-status.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 3);
+    assert!(output.stderr.contains("1 runs in"));
+
+    let output = rt.run(["--run-count", "3", "--run-all", "fixtures/exit-status.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 3);
+    assert!(output.stderr.contains("3 runs in"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn arg0() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--run-count", "1", "--arg0", "greet", "fixtures/arg0.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "greet\n");
+
+    // the cached binary is run directly as well
+    let output = rt.run(["--cached", "--arg0", "greet", "fixtures/arg0.rs"])?;
+    assert_eq!(output.stdout, "greet\n");
+
+    Ok(())
+}
+
+#[test]
+fn debug_run_without_debugger() -> Result<()> {
+    let installed = |program: &str| {
+        std::process::Command::new(program)
+            .arg("--version")
+            .output()
+            .is_ok()
+    };
+    if installed("gdb") || installed("lldb") {
+        return Ok(());
+    }
+
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--debug-run", "fixtures/hello.rs"])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("NoDebugger"));
+
+    Ok(())
+}
+
+#[test]
+fn fast() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let manifest = rt.temp_dir("fast.toml");
+
+    let output = rt.run([
+        "--fast",
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/hello.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    let content = std::fs::read_to_string(&manifest)?;
+    assert!(content.contains("[profile.dev]\nincremental = true\nopt-level = 0"));
+
+    let output = rt.run(["--fast", "fixtures/hello.rs"])?;
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(rt.temp_dir("cargo-play-target").join("debug").exists());
+
+    Ok(())
+}
+
+#[test]
+fn no_cache() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
+    let manifest = rt.temp_dir(opt.temp_dirname()).join("Cargo.toml");
+
+    let _ = rt.run(["fixtures/hello.rs"])?;
+    let modified = std::fs::metadata(&manifest)?.modified()?;
+
+    let output = rt.run(["--no-cache", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+
+    // the cache is left alone and the one-off project is gone
+    assert_eq!(std::fs::metadata(&manifest)?.modified()?, modified);
+    assert_eq!(std::fs::read_dir(&rt.scratch)?.count(), 1);
+
+    // with --clean, the cache is removed as well
+    let output = rt.run(["--no-cache", "--clean", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(!manifest.exists());
+
+    Ok(())
+}
+
+#[test]
+fn scratch() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let leftovers = |file: &str| -> Result<usize> {
+        let prefix = format!("cargo-play.{}.", Opt::with_files(vec![file]).src_hash());
+        let dirs = [rt.scratch.clone(), PathBuf::from("/dev/shm")];
+        let mut count = 0;
+        for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+            count += std::fs::read_dir(dir)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                .count();
+        }
+        Ok(count)
+    };
+
+    let output = rt.run(["--scratch", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert_eq!(leftovers("fixtures/hello.rs")?, 0);
+
+    // failed builds are cleaned up as well
+    let output = rt.run(["--scratch", "fixtures/edition.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert_eq!(leftovers("fixtures/edition.rs")?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn hash_length() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let mut opt = Opt::with_files(vec!["fixtures/hello.rs"]);
+    opt.hash_length = Some(8);
+    let project = rt.temp_dir(opt.temp_dirname());
+
+    let output = rt.run(["--hash-length", "8", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(project.join("Cargo.toml").is_file());
+
+    // the cached binary is looked up under the same short name
+    let output = rt.run(["--hash-length", "8", "--cached", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(!output.stderr.contains("Compiling"));
+
+    Ok(())
+}
+
+#[test]
+fn cargo_option_manifest_path() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run([
+        "--cargo-option=--manifest-path=Cargo.toml",
+        "fixtures/hello.rs",
+    ])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output
+        .stderr
+        .contains("--manifest-path can't be passed to cargo"));
+    assert!(output.stdout.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn new_from_template() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let snippet = rt.temp_dir("snippet.rs");
+
+    let output = rt.run(["new", snippet.to_str().unwrap()])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+
+    let output = rt.run([snippet.to_str().unwrap()])?;
+    assert_eq!(output.stdout, "Hello, world!\n");
+
+    // existing files are left alone
+    let output = rt.run(["new", "--template", "async", snippet.to_str().unwrap()])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(!std::fs::read_to_string(&snippet)?.contains("tokio"));
+
+    Ok(())
+}
+
+#[test]
+fn version_of_without_crate() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--version-of"])?;
+
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("requires the name of a crate"));
+
+    Ok(())
+}
+
+#[test]
+fn list_editions() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--list-editions"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "2015\n2018\n2021 (default)\n2024\n");
+
+    Ok(())
+}
+
+#[test]
+fn print_target_dir() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
+    let project = rt.temp_dir(opt.temp_dirname());
+
+    let output = rt.run(["--print-target-dir", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(
+        output.stdout,
+        format!("{}\n", project.join("target").display())
+    );
+    // nothing is built
+    assert!(!project.exists());
+
+    Ok(())
+}
+
+#[test]
+fn on_success() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
+    let project = rt.temp_dir(opt.temp_dirname());
+    let hook = r#"echo "$CARGO_PLAY_PROJECT $CARGO_PLAY_BINARY""#;
+
+    let output = rt.run(["--on-success", hook, "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "Hello World!\n{} {}\n",
+            project.display(),
+            project
+                .join("target")
+                .join("debug")
+                .join(opt.package_name())
+                .display()
+        )
+    );
+
+    // not run when the program fails
+    let output = rt.run(["--on-success", hook, "fixtures/exit-status.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 3);
+    assert_eq!(output.stdout, "");
+
+    Ok(())
+}
+
+#[test]
+fn enforce_rust_version() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--enforce-rust-version", "fixtures/rust-version.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("RustVersionTooOld(\"99.0\""));
+
+    Ok(())
+}
+
+#[test]
+fn bench() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    // without `#[bench]` functions, `fn main` is the benchmark
+    let output = rt.run(["--bench", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(output.stderr.contains("bench"));
+
+    let manifest = rt.temp_dir("bench.toml");
+    let output = rt.run([
+        "--bench",
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/hello.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(std::fs::read_to_string(manifest)?.contains("harness = false"));
+
+    Ok(())
+}
+
+#[test]
+fn stdin() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let source = r#"fn main() {
+    println!("from stdin");
+}
+"#;
+
+    let projects = || -> Result<HashSet<PathBuf>> {
+        Ok(std::fs::read_dir(&rt.scratch)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("cargo-play.")
+            })
+            .map(|entry| entry.path())
+            .collect())
+    };
+    let before = projects()?;
+
+    let output = rt.run_with_stdin(["-"], source)?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "from stdin\n");
+    let created: HashSet<_> = projects()?.difference(&before).cloned().collect();
+    assert_eq!(created.len(), 1);
+
+    // the same input builds the same project
+    let output = rt.run_with_stdin(["-"], source)?;
+    assert_eq!(output.stdout, "from stdin\n");
+    let created_again: HashSet<_> = projects()?.difference(&before).cloned().collect();
+    assert_eq!(created_again, created);
+
+    let output = rt.run_with_stdin(["-", "fixtures/hello.rs"], source)?;
+    assert_ne!(output.status.code().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn check() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--check", "fixtures/hello.rs", "--", "ignored"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "");
+    assert!(output.stderr.contains("Checking"));
+
+    let output = rt.run(["--check", "fixtures/edition.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn target_dir() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let shared = rt.temp_dir("shared-target");
+    let opt = Opt::with_files(vec!["fixtures/hello.rs"]);
+
+    let output = rt.run([
+        "--target-dir",
+        shared.to_str().unwrap(),
+        "fixtures/hello.rs",
+    ])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(shared
+        .join("debug")
+        .join(format!("{}{}", opt.package_name(), env::consts::EXE_SUFFIX))
+        .is_file());
+    assert!(!rt.temp_dir(opt.temp_dirname()).join("target").exists());
+
+    Ok(())
+}
+
+#[test]
+fn frontmatter() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["fixtures/frontmatter.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "A\n");
+
+    let manifest = rt.temp_dir("frontmatter.toml");
+    rt.run([
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/frontmatter.rs",
+    ])?;
+    let manifest: toml::Value = toml::from_str(&std::fs::read_to_string(manifest)?).unwrap();
+    assert_eq!(manifest["dependencies"]["bitflags"].as_str(), Some("1.1.0"));
+    assert_eq!(
+        manifest["profile"]["dev"]["opt-level"].as_integer(),
+        Some(1)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn library_api() -> Result<()> {
+    let opt = Opt {
+        no_cache: true,
+        ..Opt::with_files(vec!["fixtures/exit-status.rs"])
+    };
+    let playground = Playground::new(opt);
+
+    let project = playground.prepare().unwrap();
+    assert!(project.join("Cargo.toml").is_file());
+    assert!(project.join("src/main.rs").is_file());
+
+    let status = playground.run().unwrap();
+    assert_eq!(status.code(), Some(3));
+
+    // the project of --no-cache goes away with the playground
+    drop(playground);
+    assert!(!project.exists());
+
+    Ok(())
+}
+
+#[test]
+fn cached_release_binary() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let opt = Opt {
+        release: true,
+        ..Opt::with_files(vec!["fixtures/hello.rs"])
+    };
+    let project = rt.temp_dir(opt.temp_dirname());
+
+    let output = rt.run(["--release", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(binary_path(&opt, &project, &opt.package_name()).is_file());
+
+    let output = rt.run(["--release", "--cached", "fixtures/hello.rs"])?;
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(!output.stderr.contains("Compiling"));
+    assert!(!output.stderr.contains("Finished"));
+
+    Ok(())
+}
+
+#[test]
+fn quiet() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--quiet", "fixtures/hello.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(!output.stderr.contains("Compiling"));
+    assert!(!output.stderr.contains("Running"));
+
+    Ok(())
+}
+
+#[test]
+fn debug_log() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--debug", "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("[DEBUG] cargo_play::steps: Copying"));
+
+    let output = rt.run(["fixtures/hello.rs"])?;
+    assert!(!output.stderr.contains("Copying"));
+
+    Ok(())
+}
+
+#[test]
+fn path_dependency() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["fixtures/path-dep/main.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello from a path dependency!\n");
+
+    Ok(())
+}
+
+#[test]
+fn package_name() -> Result<()> {
+    let rt = TestRuntime::new()?;
+
+    let output = rt.run(["--name", "greeter", "fixtures/package-name.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "greeter\n");
+
+    // the cached binary is looked up under the given name
+    let output = rt.run(["--name", "greeter", "--cached", "fixtures/package-name.rs"])?;
+    assert_eq!(output.stdout, "greeter\n");
+    assert!(!output.stderr.contains("Compiling"));
+
+    let output = rt.run(["--name", "Greeter Tool", "fixtures/package-name.rs"])?;
+    assert_ne!(output.status.code().unwrap(), 0);
+    assert!(output.stderr.contains("invalid package name"));
+
+    Ok(())
+}
+
+#[test]
+fn offline() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--offline", "--cargo-option=--jobs 1", "fixtures/hello.rs"])?;
+
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+
+    Ok(())
+}
+
+#[test]
+fn cross_target() -> Result<()> {
+    let rustc = std::process::Command::new("rustc").arg("-vV").output()?;
+    let host = String::from_utf8_lossy(&rustc.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: ").map(String::from))
+        .unwrap();
+
+    let rt = TestRuntime::new()?;
+    let output = rt.run(["--target", &host, "fixtures/hello.rs"])?;
+    assert_eq!(output.status.code().unwrap(), 0);
+    assert_eq!(output.stdout, "Hello World!\n");
+
+    // the cached binary is looked up under target/<triple>
+    let output = rt.run(["--target", &host, "--cached", "fixtures/hello.rs"])?;
+    assert_eq!(output.stdout, "Hello World!\n");
+    assert!(!output.stderr.contains("Compiling"));
+    assert!(!output.stderr.contains("Finished"));
+
+    Ok(())
+}
+
+#[test]
+fn unreachable_index() -> Result<()> {
+    let rt = TestRuntime::new()?;
+    let manifest = rt.temp_dir("Cargo.toml");
+    let log = rt.temp_dir("play.log");
+    let output = rt.run([
+        "--infer",
+        "--infer-version-style",
+        "caret",
+        "--index",
+        "sparse+http://127.0.0.1:9/index/",
+        "--log-file",
+        log.to_str().unwrap(),
+        "--emit-manifest",
+        manifest.to_str().unwrap(),
+        "fixtures/infer.rs",
+    ])?;
+
     assert_eq!(output.status.code().unwrap(), 0);
+    assert!(output
+        .stderr
+        .contains("unable to look up the latest version"));
+    // each lookup is reported, in the log file as well
+    assert!(output.stderr.contains("   Searching "));
+    assert!(std::fs::read_to_string(log)?.contains("   Searching "));
+    let manifest: toml::Value = toml::from_str(&std::fs::read_to_string(manifest)?).unwrap();
+    assert!(manifest["dependencies"]
+        .as_table()
+        .unwrap()
+        .values()
+        .all(|version| version.as_str() == Some("*")));
 
     Ok(())
 }